@@ -0,0 +1,178 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use filetime::FileTime;
+
+use crate::atomic::journal_path;
+use crate::chunk::{Chunk, CRC_32_ISO};
+use crate::chunk_type::ChunkType;
+use crate::png_error::PngError;
+use crate::reader::ChunkReader;
+
+/**
+journal layout:
+magic(4) | IEND offset(u64) | original file length(u64) | original bytes from IEND on | crc(u32)
+ */
+const JOURNAL_MAGIC: &[u8; 4] = b"pmJ1";
+const JOURNAL_HEADER_LEN: usize = JOURNAL_MAGIC.len() + 8 + 8;
+
+/// Adds chunks just before IEND, rewriting only the end of the file. The headers are walked to
+/// find IEND and the bytes from there on are saved in a journal first, so an interrupted append
+/// can be undone by `recover`. The modification time of the file is kept.
+pub fn append_chunks(path: &Path, chunks: &[Chunk]) -> Result<(), PngError> {
+    recover(path)?;
+    let metadata = fs::metadata(path)?;
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let end = {
+        let reader = ChunkReader::new(&mut file)?;
+        let end_type = ChunkType::try_from(*b"IEND")?;
+        let position = *reader.indices_of(&end_type).first().ok_or(PngError::MissingEnd)?;
+        reader.entries()[position].offset
+    };
+    // IEND and anything after it, such as messages written by older versions
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(end))?;
+    file.read_to_end(&mut tail)?;
+    write_journal(&journal_path(path), end, metadata.len(), &tail)?;
+
+    let contents: Vec<u8> = chunks.iter().flat_map(Chunk::as_bytes).chain(tail).collect();
+    file.seek(SeekFrom::Start(end))?;
+    file.write_all(&contents)?;
+    file.set_len(end + contents.len() as u64)?;
+    file.sync_all()?;
+    let accessed = FileTime::from_last_access_time(&metadata);
+    let modified = FileTime::from_last_modification_time(&metadata);
+    filetime::set_file_handle_times(&file, Some(accessed), Some(modified))?;
+    fs::remove_file(journal_path(path))?;
+    Ok(())
+}
+
+/// Undoes an append that was interrupted, returning true if the file had to be restored. A
+/// journal that was not completely written is dropped, the file was not touched yet then.
+pub fn recover(path: &Path) -> Result<bool, PngError> {
+    let journal = journal_path(path);
+    let bytes = match fs::read(&journal) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    let restored = match read_journal(&bytes) {
+        Some((end, len, tail)) => {
+            let mut file = OpenOptions::new().write(true).open(path)?;
+            file.seek(SeekFrom::Start(end))?;
+            file.write_all(tail)?;
+            file.set_len(len)?;
+            file.sync_all()?;
+            true
+        }
+        None => false,
+    };
+    fs::remove_file(&journal)?;
+    Ok(restored)
+}
+
+fn write_journal(path: &Path, end: u64, len: u64, tail: &[u8]) -> Result<(), PngError> {
+    let mut contents: Vec<u8> = JOURNAL_MAGIC.iter()
+        .chain(end.to_be_bytes().iter())
+        .chain(len.to_be_bytes().iter())
+        .chain(tail)
+        .copied()
+        .collect();
+    contents.extend_from_slice(&CRC_32_ISO.checksum(&contents).to_be_bytes());
+    let mut journal = File::create(path)?;
+    journal.write_all(&contents)?;
+    journal.sync_all()?;
+    Ok(())
+}
+
+// IEND offset, original length and original tail of a complete journal
+fn read_journal(bytes: &[u8]) -> Option<(u64, u64, &[u8])> {
+    if bytes.len() < JOURNAL_HEADER_LEN + 4 || !bytes.starts_with(JOURNAL_MAGIC) {
+        return None;
+    }
+    let (contents, crc) = bytes.split_at(bytes.len() - 4);
+    if CRC_32_ISO.checksum(contents) != u32::from_be_bytes(crc.try_into().ok()?) {
+        return None;
+    }
+    let end = u64::from_be_bytes(contents[4..12].try_into().ok()?);
+    let len = u64::from_be_bytes(contents[12..20].try_into().ok()?);
+    Some((end, len, &contents[JOURNAL_HEADER_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn chunk_types(path: &Path) -> Vec<String> {
+        let png = Png::from_file(path).unwrap();
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    fn testing_file(dir: &Path, chunks: Vec<Chunk>) -> PathBuf {
+        let path = dir.join("image.png");
+        fs::write(&path, Png::from_chunks(chunks).as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = testing_file(dir.path(), vec![chunk("IHDR", "h"), chunk("IDAT", "x"), chunk("IEND", "")]);
+        filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000_000, 0)).unwrap();
+
+        append_chunks(&path, &[chunk("ruSt", "one"), chunk("ruSt", "two")]).unwrap();
+        assert_eq!(chunk_types(&path), vec!["IHDR", "IDAT", "ruSt", "ruSt", "IEND"]);
+        let modified = FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        assert_eq!(modified.unix_seconds(), 1_000_000_000);
+        assert!(!journal_path(&path).exists());
+    }
+
+    #[test]
+    fn test_keeps_chunks_after_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = testing_file(dir.path(), vec![chunk("IHDR", "h"), chunk("IEND", ""), chunk("ruSt", "old")]);
+        append_chunks(&path, &[chunk("ruSt", "new")]).unwrap();
+        assert_eq!(chunk_types(&path), vec!["IHDR", "ruSt", "IEND", "ruSt"]);
+
+        let path = testing_file(dir.path(), vec![chunk("IHDR", "h")]);
+        assert!(matches!(append_chunks(&path, &[chunk("ruSt", "new")]), Err(PngError::MissingEnd)));
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = testing_file(dir.path(), vec![chunk("IHDR", "h"), chunk("IEND", "")]);
+        let original = fs::read(&path).unwrap();
+        let end = original.len() as u64 - 12;
+        write_journal(&journal_path(&path), end, original.len() as u64, &original[end as usize..]).unwrap();
+        // the append stopped half way through the new chunk
+        let mut damaged = original[..end as usize].to_vec();
+        damaged.extend_from_slice(&chunk("ruSt", "interrupted").as_bytes()[..10]);
+        fs::write(&path, &damaged).unwrap();
+
+        assert!(recover(&path).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert!(!journal_path(&path).exists());
+        assert!(!recover(&path).unwrap());
+    }
+
+    #[test]
+    fn test_incomplete_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = testing_file(dir.path(), vec![chunk("IHDR", "h"), chunk("IEND", "")]);
+        let original = fs::read(&path).unwrap();
+        fs::write(journal_path(&path), &JOURNAL_MAGIC[..]).unwrap();
+
+        assert!(!recover(&path).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert!(!journal_path(&path).exists());
+    }
+}
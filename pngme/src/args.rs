@@ -1,11 +1,15 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::batch::DEFAULT_NAME_TEMPLATE;
+use crate::compression::DEFAULT_MAX_OUTPUT;
 
 /// simple program to hide secret message in png
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
+#[clap(after_help = "File paths may be - to read from stdin or write to stdout.")]
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Command,
@@ -13,18 +17,48 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Command {
-    /// encode: hide secret into png / encode <file_path> <chunk_type> <message> <out_file_path>
+    /// encode: hide secret into png / encode <file_path> <chunk_type> <message|--message-file path> <out_file_path|--out path|--in-place>
     Encode(EncodeArgs),
     /// decode: get secret message by chunk type / decode <file_path> <chunk_type>
     Decode(DecodeArgs),
+    /// update: replace a secret message where it is / update <file_path> <chunk_type> [out_path] [--message text|--message-file path]
+    Update(UpdateArgs),
     /// remove: remove secret message by chunk type / remove <file_path> <chunk_type>
     Remove(RemoveArgs),
+    /// strip: remove metadata chunks and data after IEND / strip <file_path>
+    Strip(StripArgs),
     /// print: print all chunk type / print <file_path>
     Print(PrintArgs),
+    /// inspect: show offset, flags, CRCs and contents of every chunk / inspect <file_path>
+    Inspect(InspectArgs),
+    /// dump: show the bytes of a chunk as hex / dump <file_path> <chunk_type|--offset n>
+    Dump(DumpArgs),
+    /// transplant: copy ancillary chunks from one png into another / transplant <source_path> <file_path> [out_path]
+    Transplant(TransplantArgs),
+    /// diff: compare the chunks of two pngs / diff <old_path> <new_path>
+    Diff(DiffArgs),
+    /// unpack: write every chunk to a file plus a manifest / unpack <file_path> <out_dir>
+    Unpack(UnpackArgs),
+    /// pack: rebuild a png from an unpacked directory / pack <dir> <out_path>
+    Pack(PackArgs),
+    /// validate: check the structure and CRCs of a png / validate <file_path>
+    Validate(ValidateArgs),
+    /// repair: fix CRCs, lengths and chunk order of a damaged png / repair <file_path>
+    Repair(RepairArgs),
+    /// scan: look for hidden messages / scan <file_path>
+    Scan(ScanArgs),
+    /// messages: list messages with their index, size and offset / messages <file_path> [chunk_type]
+    Messages(MessagesArgs),
+    /// keygen: generate a key pair for encrypted messages or signing / keygen [out_path]
+    Keygen(KeygenArgs),
+    /// sign: sign the image and selected messages / sign <file_path> <key_path> [out_path]
+    Sign(SignArgs),
+    /// verify: check the signature of an image / verify <file_path> --public-key <key>
+    Verify(VerifyArgs),
 }
 
-#[clap(author, version, about, long_about = None)]
 #[derive(Args)]
+#[clap(author, version, about, long_about = None)]
 pub struct EncodeArgs {
     /// input file_path
     #[clap(value_parser)]
@@ -33,36 +67,541 @@ pub struct EncodeArgs {
     #[clap(value_parser)]
     pub chunk_type: String,
 
+    /// the message to hide, leave it out when it comes from --message-file
+    #[clap(value_parser, required_unless_present = "message_file", conflicts_with = "message_file")]
+    pub message: Option<String>,
+
+    #[clap(value_parser, conflicts_with = "out_dir")]
+    pub out_path: Option<PathBuf>,
+
+    /// read the message from a file, or from stdin with -
+    #[clap(long, value_parser)]
+    pub message_file: Option<PathBuf>,
+
+    /// same as out_path, for when the message comes from --message-file
+    #[clap(long = "out", value_parser, conflicts_with_all = ["out_path", "out_dir"])]
+    pub out: Option<PathBuf>,
+
+    /// write the result back to the input file instead of out_path, only the end of the file is
+    /// rewritten unless --backup is given. The message goes before IEND either way.
+    #[clap(long, conflicts_with_all = ["out_path", "out", "out_dir"])]
+    pub in_place: bool,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+
+    /// public key (or key file) allowed to decrypt the message, may be repeated
+    #[clap(long = "recipient", value_parser)]
+    pub recipients: Vec<String>,
+
+    /// compress the message, auto picks the smallest codec and skips compression if it does not help
+    #[clap(long, value_enum)]
+    pub compress: Option<CompressArg>,
+
+    /// split the message into chunks of at most this many bytes
+    #[clap(long, value_parser)]
+    pub split: Option<usize>,
+
+    /// allow chunk types decoders may reject or read as a standard chunk
+    #[clap(long)]
+    pub force: bool,
+
+    /// add Reed-Solomon error correction with this many parity bytes per 255-byte block,
+    /// each block can repair half as many damaged bytes
+    #[clap(long, value_parser)]
+    pub ecc: Option<u8>,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+}
+
+impl EncodeArgs {
+    /// The output file given as out_path or with --out
+    pub fn out_path(&self) -> Option<&Path> {
+        self.out_path.as_deref().or(self.out.as_deref())
+    }
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct UpdateArgs {
     #[clap(value_parser)]
-    pub message: String,
+    pub file_path: PathBuf,
 
+    #[clap(value_parser)]
+    pub chunk_type: String,
+
+    /// where to write the result, the input file when left out
     #[clap(value_parser)]
     pub out_path: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub message: MessageArgs,
+
+    /// which message to replace when several share the chunk type, starting at 0
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub index: usize,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+
+    /// allow chunk types decoders may reject or read as a standard chunk
+    #[clap(long)]
+    pub force: bool,
+
+    /// public key (or key file) allowed to decrypt the message, may be repeated
+    #[clap(long = "recipient", value_parser)]
+    pub recipients: Vec<String>,
+
+    /// compress the message, auto picks the smallest codec and skips compression if it does not help
+    #[clap(long, value_enum)]
+    pub compress: Option<CompressArg>,
+
+    /// add Reed-Solomon error correction with this many parity bytes per 255-byte block,
+    /// each block can repair half as many damaged bytes
+    #[clap(long, value_parser)]
+    pub ecc: Option<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CompressArg {
+    Deflate,
+    Zstd,
+    Auto,
 }
 
-#[clap(author, version, about, long_about = None)]
 #[derive(Args)]
+#[clap(author, version, about, long_about = None)]
 pub struct DecodeArgs {
     #[clap(value_parser)]
     pub file_path: PathBuf,
 
     #[clap(value_parser)]
     pub chunk_type: String,
+
+    /// identity file used to decrypt an encrypted message
+    #[clap(long, value_parser)]
+    pub identity: Option<PathBuf>,
+
+    /// largest message in bytes that a compressed message may expand to
+    #[clap(long, value_parser, default_value_t = DEFAULT_MAX_OUTPUT)]
+    pub max_output: usize,
+
+    /// accept message chunks with a bad CRC so error correction can repair them
+    #[clap(long)]
+    pub relaxed_crc: bool,
+
+    /// which message to decode when several share the chunk type, starting at 0
+    #[clap(long, value_parser, conflicts_with = "all")]
+    pub index: Option<usize>,
+
+    /// decode the chunk starting at this byte offset, as listed by messages or scan
+    #[clap(long, value_parser, conflicts_with_all = ["all", "index"])]
+    pub offset: Option<u64>,
+
+    /// decode every message of the chunk type
+    #[clap(long)]
+    pub all: bool,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
 }
 
-#[clap(author, version, about, long_about = None)]
 #[derive(Args)]
+#[clap(author, version, about, long_about = None)]
 pub struct RemoveArgs {
     #[clap(value_parser)]
     pub file_path: PathBuf,
 
     #[clap(value_parser)]
     pub chunk_type: String,
+
+    /// which chunk to remove when several share the chunk type, starting at 0, the pieces of a
+    /// split message are removed together
+    #[clap(long, value_parser, conflicts_with = "all")]
+    pub index: Option<usize>,
+
+    /// remove every chunk of the chunk type
+    #[clap(long)]
+    pub all: bool,
+
+    /// allow removing critical chunks
+    #[clap(long)]
+    pub force: bool,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct StripArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// remove every ancillary chunk, the default when no other chunks are chosen
+    #[clap(long)]
+    pub all_ancillary: bool,
+
+    /// remove private ancillary chunks
+    #[clap(long)]
+    pub private_only: bool,
+
+    /// remove tEXt, zTXt and iTXt chunks
+    #[clap(long)]
+    pub text: bool,
+
+    /// remove eXIf chunks
+    #[clap(long)]
+    pub exif: bool,
+
+    /// chunk type to remove, may be repeated
+    #[clap(long = "remove", value_parser)]
+    pub remove: Vec<String>,
+
+    /// chunk type to keep even if it would be removed, may be repeated
+    #[clap(long = "keep", value_parser)]
+    pub keep: Vec<String>,
+
+    /// TOML file with presets, keep and remove lists, combined with the options above
+    #[clap(long, value_parser)]
+    pub policy: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
 }
 
+#[derive(Args)]
 #[clap(author, version, about, long_about = None)]
+pub struct RepairArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// list the fixes without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+}
+
 #[derive(Args)]
+#[clap(author, version, about, long_about = None)]
 pub struct PrintArgs {
     #[clap(value_parser)]
     pub file_path: PathBuf,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct InspectArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// colour the table, auto only does so on a terminal and when NO_COLOR is not set
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct DumpArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    #[clap(value_parser, required_unless_present = "offset")]
+    pub chunk_type: Option<String>,
+
+    /// which chunk to dump when several share the chunk type, starting at 0
+    #[clap(long, value_parser, default_value_t = 0, conflicts_with = "offset")]
+    pub index: usize,
+
+    /// dump the chunk at this byte offset of the file instead of choosing it by type
+    #[clap(long, value_parser, conflicts_with = "chunk_type")]
+    pub offset: Option<usize>,
+
+    /// only dump these bytes of the chunk data, as start..end, start.. or ..end
+    #[clap(long, value_parser)]
+    pub range: Option<String>,
+
+    /// write the chunk data to stdout as it is instead of as hex
+    #[clap(long)]
+    pub raw: bool,
+
+    /// colour the fields, auto only does so on a terminal and when NO_COLOR is not set
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct TransplantArgs {
+    /// image to copy the chunks from
+    #[clap(value_parser)]
+    pub source_path: PathBuf,
+
+    /// image to copy the chunks into
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    #[clap(value_parser)]
+    pub out_path: Option<PathBuf>,
+
+    /// also copy chunks marked unsafe to copy, which may no longer match the image data
+    #[clap(long = "unsafe")]
+    pub copy_unsafe: bool,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct DiffArgs {
+    #[clap(value_parser)]
+    pub old_path: PathBuf,
+
+    #[clap(value_parser)]
+    pub new_path: PathBuf,
+
+    /// also decode both images and compare their pixels
+    #[clap(long)]
+    pub pixels: bool,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct UnpackArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// directory the chunk files and manifest are written to
+    #[clap(value_parser)]
+    pub out_dir: PathBuf,
+
+    #[clap(long, value_enum, default_value_t = ManifestArg::Json)]
+    pub manifest: ManifestArg,
+
+    /// replace the manifest and chunk files of an earlier unpack into the same directory
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ManifestArg {
+    Json,
+    Toml,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct PackArgs {
+    /// directory written by unpack
+    #[clap(value_parser)]
+    pub dir: PathBuf,
+
+    #[clap(value_parser)]
+    pub out_path: PathBuf,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct ValidateArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct ScanArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    #[clap(flatten)]
+    pub batch: BatchArgs,
+
+    #[clap(flatten)]
+    pub format: FormatArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct MessagesArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// only list chunks of this type, by default every private chunk is listed
+    #[clap(value_parser)]
+    pub chunk_type: Option<String>,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct KeygenArgs {
+    /// identity file to create, the public key is written next to it with a .pub extension
+    #[clap(value_parser)]
+    pub out_path: Option<PathBuf>,
+
+    /// generate an Ed25519 signing key instead of an encryption key
+    #[clap(long)]
+    pub signing: bool,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct SignArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// signing key file created by keygen --signing
+    #[clap(value_parser)]
+    pub key_path: PathBuf,
+
+    #[clap(value_parser)]
+    pub out_path: Option<PathBuf>,
+
+    /// message chunk type to include in the signature, may be repeated
+    #[clap(long = "chunk-type", value_parser)]
+    pub chunk_types: Vec<String>,
+
+    #[clap(flatten)]
+    pub backup: BackupArgs,
+}
+
+#[derive(Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct VerifyArgs {
+    #[clap(value_parser)]
+    pub file_path: PathBuf,
+
+    /// public key (or key file) the image must be signed by, a signature made with any other key
+    /// fails even when it is intact
+    #[clap(long = "public-key", value_parser)]
+    pub public_key: String,
+}
+
+/// where results go when file_path is a directory or glob pattern
+#[derive(Args)]
+pub struct OutputArgs {
+    /// write results into this directory, mirroring the layout of the input directory
+    #[clap(long, value_parser)]
+    pub out_dir: Option<PathBuf>,
+
+    /// output file name inside --out-dir, {name}, {stem} and {ext} are replaced
+    #[clap(long, value_parser, default_value = DEFAULT_NAME_TEMPLATE)]
+    pub name: String,
+}
+
+/// where the message to hide comes from
+#[derive(Args)]
+pub struct MessageArgs {
+    /// the message, read from stdin when neither this nor --message-file is given
+    #[clap(long, value_parser, conflicts_with = "message_file")]
+    pub message: Option<String>,
+
+    /// read the message from a file, or from stdin with -
+    #[clap(long, value_parser)]
+    pub message_file: Option<PathBuf>,
+}
+
+/// how many files of a directory or glob pattern are processed at once
+#[derive(Args)]
+pub struct BatchArgs {
+    /// number of files processed in parallel for directories and glob patterns, 0 uses every CPU
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub jobs: usize,
+}
+
+/// how results are printed
+#[derive(Args)]
+pub struct FormatArgs {
+    /// output format, json prints one JSON object per file (NDJSON for directories and glob patterns)
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+impl FormatArgs {
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+}
+
+/// what happens to files that get overwritten
+#[derive(Args)]
+pub struct BackupArgs {
+    /// keep a .bak copy of any file that gets overwritten
+    #[clap(long = "backup", id = "backup")]
+    pub keep: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
 }
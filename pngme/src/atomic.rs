@@ -0,0 +1,169 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+use tempfile::NamedTempFile;
+
+use crate::png_error::PngError;
+
+/// Writes the file through a temporary file in the same directory that is then renamed over it,
+/// so a crash leaves either the old or the new contents. When the file already exists its
+/// permissions and modification time are kept, and with `backup` a `.bak` copy is made first.
+/// A new file gets the permissions the umask allows, as if it had been created directly.
+pub fn write<P: AsRef<Path>>(path: P, contents: &[u8], backup: bool) -> Result<(), PngError> {
+    write_with(path.as_ref(), contents, backup, false)
+}
+
+/// Same as `write` for files holding secrets, which are readable by their owner only even when
+/// they replace a file with wider permissions
+pub fn write_private<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<(), PngError> {
+    write_with(path.as_ref(), contents, false, true)
+}
+
+fn write_with(path: &Path, contents: &[u8], backup: bool, private: bool) -> Result<(), PngError> {
+    let mut path = path.to_path_buf();
+    let existing = fs::metadata(&path).ok();
+    if let Some(metadata) = &existing {
+        // replace the file a symlink points to, not the link itself
+        path = fs::canonicalize(&path)?;
+        if backup {
+            let backup_path = backup_path(&path);
+            fs::copy(&path, &backup_path)?;
+            copy_times(metadata, &fs::File::open(&backup_path)?)?;
+        }
+    }
+
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut temp = create_temp(&dir, private)?;
+    temp.write_all(contents)?;
+    temp.as_file().sync_all()?;
+    if let Some(metadata) = &existing {
+        if !private {
+            temp.as_file().set_permissions(metadata.permissions())?;
+        }
+        copy_times(metadata, temp.as_file())?;
+    }
+    temp.persist(&path).map_err(|err| PngError::IOError(err.error))?;
+    // the rename is only durable once the directory holding it is synced too
+    #[cfg(unix)]
+    fs::File::open(&dir)?.sync_all()?;
+    Ok(())
+}
+
+// temporary files are owner-only by default, ask for 0666 so the umask decides instead unless
+// the file holds a secret
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn create_temp(dir: &Path, private: bool) -> io::Result<NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    {
+        let mode = if private { 0o600 } else { 0o666 };
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(mode));
+    }
+    builder.tempfile_in(dir)
+}
+
+/// Path of the journal kept next to a file while `append` changes it in place, reading the file
+/// fails while it is there
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = OsString::from(path.as_os_str());
+    journal.push(".pngme-journal");
+    PathBuf::from(journal)
+}
+
+/// Path of the backup copy made by `write`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = OsString::from(path.as_os_str());
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+fn copy_times(metadata: &fs::Metadata, file: &fs::File) -> Result<(), PngError> {
+    let accessed = FileTime::from_last_access_time(metadata);
+    let modified = FileTime::from_last_modification_time(metadata);
+    filetime::set_file_handle_times(file, Some(accessed), Some(modified))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.png");
+        write(&path, b"contents", true).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"contents");
+        assert!(!backup_path(&path).exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_overwrite_keeps_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"old").unwrap();
+        let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+
+        write(&path, b"new", false).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(metadata.permissions().readonly());
+        assert_eq!(FileTime::from_last_modification_time(&metadata), mtime);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_file_follows_umask() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let created = dir.path().join("created.png");
+        fs::File::create(&created).unwrap();
+        let path = dir.path().join("new.png");
+
+        write(&path, b"contents", false).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), mode(&created));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"secret").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_overwrite_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"old").unwrap();
+
+        write(&path, b"new", true).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"old");
+    }
+}
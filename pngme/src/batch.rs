@@ -0,0 +1,469 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::{self, IsTerminal, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use walkdir::WalkDir;
+
+use crate::png_error::PngError;
+
+/// Default naming template for batch output, keeps the input file name
+pub const DEFAULT_NAME_TEMPLATE: &str = "{name}";
+
+/// The files an input path expands to
+#[derive(Debug)]
+pub struct Inputs {
+    /// directory the files are relative to when mirrored into an output directory
+    pub root: PathBuf,
+    pub files: Vec<PathBuf>,
+    /// true for directories and glob patterns, even if they matched a single file
+    pub is_batch: bool,
+    /// paths that could not be listed, such as symlink loops, reported like failed files
+    pub unreadable: Vec<(PathBuf, PngError)>,
+}
+
+/// Outcome of running a command over several files
+#[derive(Debug, Default)]
+pub struct Report {
+    pub succeeded: usize,
+    pub failures: Vec<(PathBuf, PngError)>,
+}
+
+/// Expands a directory (recursively, PNG files only) or a glob pattern into files,
+/// anything else is taken as a single file
+pub fn expand(input: &Path) -> Result<Inputs, PngError> {
+    if !is_batch(input) {
+        let root = input.parent().map(Path::to_path_buf).unwrap_or_default();
+        return Ok(Inputs { root, files: vec![input.to_path_buf()], is_batch: false, unreadable: Vec::new() });
+    }
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    if input.is_dir() {
+        for entry in WalkDir::new(input).follow_links(true) {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() && has_png_extension(entry.path()) => {
+                    files.push(entry.into_path())
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    let path = err.path().unwrap_or(input).to_path_buf();
+                    unreadable.push((path, PngError::InputError(err.to_string())));
+                }
+            }
+        }
+        files.sort();
+        return Ok(Inputs { root: input.to_path_buf(), files, is_batch: true, unreadable });
+    }
+    let pattern = input.to_string_lossy();
+    let paths = glob::glob(&pattern).map_err(|err| PngError::InputError(err.to_string()))?;
+    for path in paths {
+        match path {
+            Ok(path) if path.is_file() => files.push(path),
+            Ok(_) => {}
+            Err(err) => unreadable.push((err.path().to_path_buf(), PngError::InputError(err.to_string()))),
+        }
+    }
+    if files.is_empty() && unreadable.is_empty() {
+        return Err(PngError::InputError(format!("{} matches no files", pattern)));
+    }
+    files.sort();
+    Ok(Inputs { root: glob_root(input), files, is_batch: true, unreadable })
+}
+
+/// True when the input is a directory or a glob pattern rather than a single file, a file whose
+/// name merely contains wildcard characters is a single file
+pub fn is_batch(input: &Path) -> bool {
+    input.is_dir() || (!input.exists() && is_glob(&input.to_string_lossy()))
+}
+
+impl Inputs {
+    /// Where the output for `file` goes inside `out_dir`, keeping its path relative to the root
+    /// and naming it with the template (`{name}`, `{stem}` and `{ext}` are replaced)
+    pub fn output_path(&self, file: &Path, out_dir: &Path, template: &str) -> PathBuf {
+        let relative = file.strip_prefix(&self.root).unwrap_or(file);
+        let name = apply_template(relative, template);
+        let mut res = out_dir.to_path_buf();
+        if let Some(parent) = relative.parent() {
+            res.push(parent);
+        }
+        res.push(name);
+        res
+    }
+}
+
+impl Report {
+    pub fn record(&mut self, file: &Path, result: Result<(), PngError>) {
+        match result {
+            Ok(()) => self.succeeded += 1,
+            Err(err) => self.failures.push((file.to_path_buf(), err)),
+        }
+    }
+
+    /// Prints the totals and the failed files, the totals go to stderr with `json` so stdout
+    /// only carries records
+    pub fn print_summary(&self, json: bool) {
+        let total = self.succeeded + self.failures.len();
+        let summary = format!("processed {} files: {} succeeded, {} failed", total, self.succeeded, self.failures.len());
+        match json {
+            true => eprintln!("{}", summary),
+            false => println!("{}", summary),
+        }
+        for (file, err) in self.failures.iter() {
+            eprintln!("  {}: {}", file.display(), err);
+        }
+    }
+}
+
+/// Runs `f` on every file the input expands to. A single file behaves exactly like before;
+/// in batch mode up to `jobs` files (0 for one per CPU) are processed in parallel, each one's
+/// output is printed in input order, and errors are collected and summarised at the end.
+/// With `json` each file is expected to print one JSON line, failed files that printed nothing
+/// get a `{"file", "error"}` line instead, a single file as well as a whole batch, so the output
+/// always reads as NDJSON.
+pub fn for_each_input<F>(input: &Path, jobs: usize, json: bool, f: F) -> Result<(), PngError>
+where
+    F: Fn(&Inputs, &Path, &mut dyn Write) -> Result<(), PngError> + Sync,
+{
+    let mut inputs = expand(input)?;
+    if !inputs.is_batch {
+        let file = &inputs.files[0];
+        if !json {
+            return f(&inputs, file, &mut io::stdout().lock());
+        }
+        let mut output = Vec::new();
+        let result = f(&inputs, file, &mut output);
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&output)?;
+        if let Err(err) = &result {
+            if output.is_empty() {
+                write_error_record(&mut stdout, file, err);
+            }
+        }
+        return result;
+    }
+    let jobs = match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let total = inputs.files.len();
+    let pool = Pool {
+        state: Mutex::new(PoolState { next: 0, printed: 0, finished: BTreeMap::new() }),
+        changed: Condvar::new(),
+        // finished files waiting for an earlier one to be printed are capped, so memory stays bounded
+        window: jobs * 2,
+        total,
+    };
+    let progress = io::stderr().is_terminal();
+    let mut report = Report::default();
+    let unreadable = std::mem::take(&mut inputs.unreadable);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(total) {
+            scope.spawn(|| pool.work(|file, out| f(&inputs, file, out), &inputs.files));
+        }
+        for (index, file) in inputs.files.iter().enumerate() {
+            let (output, result) = pool.wait_for(index, |completed| {
+                if progress {
+                    eprint!("\r[{}/{}]", completed, total);
+                }
+            });
+            if progress {
+                eprint!("\r\x1b[K");
+            }
+            let mut stdout = io::stdout().lock();
+            if !json {
+                writeln!(stdout, "== {} ==", file.display()).ok();
+            }
+            stdout.write_all(&output).ok();
+            if let Err(err) = &result {
+                // a file that failed without printing its own record still gets a line
+                print_error(&mut stdout, file, err, json && output.is_empty());
+            }
+            report.record(file, result);
+        }
+    });
+    for (path, err) in unreadable {
+        print_error(&mut io::stdout().lock(), &path, &err, json);
+        report.record(&path, Err(err));
+    }
+
+    report.print_summary(json);
+    match report.failures.len() {
+        0 => Ok(()),
+        failed => Err(PngError::BatchFailed(failed)),
+    }
+}
+
+// reports a failed file on stderr, and on stdout as a `{"file", "error"}` record with `json`
+fn print_error(stdout: &mut dyn Write, file: &Path, err: &PngError, json: bool) {
+    if json {
+        write_error_record(stdout, file, err);
+    }
+    eprintln!("Error: {}", err);
+}
+
+fn write_error_record(out: &mut dyn Write, file: &Path, err: &PngError) {
+    let record = serde_json::json!({ "file": file, "error": err.to_string() });
+    writeln!(out, "{}", record).ok();
+}
+
+struct Pool {
+    state: Mutex<PoolState>,
+    changed: Condvar,
+    window: usize,
+    total: usize,
+}
+
+struct PoolState {
+    // index of the next file to hand to a worker
+    next: usize,
+    // number of files whose output has been printed
+    printed: usize,
+    finished: BTreeMap<usize, (Vec<u8>, Result<(), PngError>)>,
+}
+
+impl Pool {
+    // takes files until none are left, never running further ahead of the printer than the window
+    fn work<G>(&self, f: G, files: &[PathBuf])
+    where
+        G: Fn(&Path, &mut dyn Write) -> Result<(), PngError>,
+    {
+        loop {
+            let index = {
+                let mut state = self.state.lock().unwrap();
+                while state.next < self.total && state.next >= state.printed + self.window {
+                    state = self.changed.wait(state).unwrap();
+                }
+                if state.next >= self.total {
+                    return;
+                }
+                state.next += 1;
+                state.next - 1
+            };
+            let mut output = Vec::new();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(&files[index], &mut output)))
+                .unwrap_or(Err(PngError::Panicked));
+            self.state.lock().unwrap().finished.insert(index, (output, result));
+            self.changed.notify_all();
+        }
+    }
+
+    // blocks until the file at `index` is finished, reporting how many files are done meanwhile
+    fn wait_for<P>(&self, index: usize, progress: P) -> (Vec<u8>, Result<(), PngError>)
+    where
+        P: Fn(usize),
+    {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            progress(state.printed + state.finished.len());
+            if let Some(finished) = state.finished.remove(&index) {
+                state.printed = index + 1;
+                self.changed.notify_all();
+                return finished;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn has_png_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+}
+
+// the leading directories of a pattern that contain no wildcards
+fn glob_root(pattern: &Path) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in pattern.components() {
+        if let Component::Normal(part) = component {
+            if is_glob(&part.to_string_lossy()) {
+                break;
+            }
+        }
+        root.push(component);
+    }
+    if root == pattern {
+        root.pop();
+    }
+    root
+}
+
+fn apply_template(file: &Path, template: &str) -> String {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = file.extension().unwrap_or_default().to_string_lossy();
+    template
+        .replace("{name}", &name)
+        .replace("{stem}", &stem)
+        .replace("{ext}", &ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn testing_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for file in ["a.png", "b.PNG", "notes.txt", "sub/c.png"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_expand_single_file() {
+        let inputs = expand(Path::new("images/dice.png")).unwrap();
+        assert!(!inputs.is_batch);
+        assert_eq!(inputs.files, vec![PathBuf::from("images/dice.png")]);
+    }
+
+    #[test]
+    fn test_expand_directory() {
+        let dir = testing_tree();
+        let inputs = expand(dir.path()).unwrap();
+        assert!(inputs.is_batch);
+        let names: Vec<PathBuf> = inputs.files.iter()
+            .map(|file| file.strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(names, vec![PathBuf::from("a.png"), PathBuf::from("b.PNG"), PathBuf::from("sub/c.png")]);
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = testing_tree();
+        let inputs = expand(&dir.path().join("*.png")).unwrap();
+        assert!(inputs.is_batch);
+        assert_eq!(inputs.root, dir.path());
+        assert_eq!(inputs.files, vec![dir.path().join("a.png")]);
+    }
+
+    #[test]
+    fn test_file_named_like_a_glob() {
+        let dir = testing_tree();
+        let path = dir.path().join("img[1].png");
+        fs::write(&path, b"png").unwrap();
+        let inputs = expand(&path).unwrap();
+        assert!(!inputs.is_batch);
+        assert_eq!(inputs.files, vec![path]);
+    }
+
+    #[test]
+    fn test_glob_without_matches() {
+        let dir = testing_tree();
+        assert!(matches!(expand(&dir.path().join("*.jpeg")), Err(PngError::InputError(_))));
+    }
+
+    #[test]
+    fn test_output_path() {
+        let dir = testing_tree();
+        let inputs = expand(dir.path()).unwrap();
+        let output = inputs.output_path(&dir.path().join("sub/c.png"), Path::new("out"), "{stem}-signed.{ext}");
+        assert_eq!(output, PathBuf::from("out/sub/c-signed.png"));
+
+        let output = inputs.output_path(&dir.path().join("a.png"), Path::new("out"), DEFAULT_NAME_TEMPLATE);
+        assert_eq!(output, PathBuf::from("out/a.png"));
+    }
+
+    #[test]
+    fn test_for_each_input_collects_errors() {
+        let dir = testing_tree();
+        let seen = AtomicUsize::new(0);
+        let result = for_each_input(dir.path(), 1, false, |_, file, _| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            match file.ends_with("a.png") {
+                true => Err(PngError::ChunkError),
+                false => Ok(()),
+            }
+        });
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+        assert!(matches!(result, Err(PngError::BatchFailed(1))));
+    }
+
+    #[test]
+    fn test_pool_keeps_input_order() {
+        let files: Vec<PathBuf> = (0..50).map(|i| PathBuf::from(i.to_string())).collect();
+        let pool = Pool {
+            state: Mutex::new(PoolState { next: 0, printed: 0, finished: BTreeMap::new() }),
+            changed: Condvar::new(),
+            window: 8,
+            total: files.len(),
+        };
+        let calls = AtomicUsize::new(0);
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let mut outputs = Vec::new();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| pool.work(|file, out| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let running = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_flight.fetch_max(running, Ordering::SeqCst);
+                    // later files finish first to shuffle completion order
+                    let n: u64 = file.to_string_lossy().parse().unwrap();
+                    thread::sleep(std::time::Duration::from_micros((50 - n) * 20));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    write!(out, "{}", n)?;
+                    match n % 7 {
+                        0 => Err(PngError::ChunkError),
+                        _ => Ok(()),
+                    }
+                }, &files));
+            }
+            for index in 0..files.len() {
+                let (output, result) = pool.wait_for(index, |_| {});
+                let state = pool.state.lock().unwrap();
+                assert!(state.next <= index + 1 + pool.window);
+                drop(state);
+                outputs.push((String::from_utf8(output).unwrap(), result.is_err()));
+            }
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 50);
+        // files really ran side by side, never on more workers than were started
+        assert!((2..=4).contains(&most_in_flight.load(Ordering::SeqCst)));
+        for (index, (output, failed)) in outputs.iter().enumerate() {
+            assert_eq!(output, &index.to_string());
+            assert_eq!(*failed, index % 7 == 0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop_is_a_failed_file() {
+        let dir = testing_tree();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+        let inputs = expand(dir.path()).unwrap();
+        assert_eq!(inputs.files.len(), 3);
+        assert_eq!(inputs.unreadable.len(), 1);
+
+        let seen = AtomicUsize::new(0);
+        let result = for_each_input(dir.path(), 1, false, |_, _, _| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+        assert!(matches!(result, Err(PngError::BatchFailed(1))));
+    }
+
+    #[test]
+    fn test_pool_survives_panics() {
+        let dir = testing_tree();
+        let result = for_each_input(dir.path(), 2, false, |_, file, _| {
+            if file.ends_with("b.PNG") {
+                panic!("broken file");
+            }
+            Ok(())
+        });
+        assert!(matches!(result, Err(PngError::BatchFailed(1))));
+    }
+}
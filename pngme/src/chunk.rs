@@ -1,4 +1,5 @@
 use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
 
 use anyhow::Result;
 use crc::{Crc, CRC_32_ISO_HDLC};
@@ -7,8 +8,10 @@ use crate::chunk_type::ChunkType;
 use crate::png_error::PngError;
 
 pub const CRC_32_ISO: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+/// largest data length the PNG spec allows in a single chunk (2^31 - 1)
+pub const MAX_DATA_LENGTH: usize = (1 << 31) - 1;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chunk {
     data_length: u32,
     chunk_type: ChunkType,
@@ -17,17 +20,25 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// # Panics
+    /// When the data is longer than `MAX_DATA_LENGTH`, `try_new` returns an error instead
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        let len = data.len();
+        Chunk::try_new(chunk_type, data).unwrap_or_else(|_| panic!("{} bytes of chunk data exceed MAX_DATA_LENGTH", len))
+    }
+    /// Same as `new`, failing with `ChunkTooLarge` when the data is longer than `MAX_DATA_LENGTH`
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk, PngError> {
+        let data_length = data_length(data.len()).ok_or(PngError::ChunkTooLarge(data.len()))?;
         let crc_source = chunk_type.to_string().as_bytes().iter()
             .chain(data.iter())
             .copied()
             .collect::<Vec<u8>>();
-        Chunk {
-            data_length: data.len() as u32,
+        Ok(Chunk {
+            data_length,
             chunk_type,
             data,
             crc: CRC_32_ISO.checksum(&crc_source[..]),
-        }
+        })
     }
     pub fn length(&self) -> u32 {
         self.data_length
@@ -41,10 +52,65 @@ impl Chunk {
     pub fn crc(&self) -> u32 {
         self.crc
     }
+    /// CRC of the chunk type and data, which `crc` may not match for chunks parsed without
+    /// checking their CRC
+    pub fn computed_crc(&self) -> u32 {
+        self.as_chunk_ref().computed_crc()
+    }
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+    /// Replaces the data, recomputing length and CRC
+    ///
+    /// # Panics
+    /// When the data is longer than `MAX_DATA_LENGTH`, `try_set_data` returns an error instead
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.data = data;
+        self.update();
+    }
+    /// Same as `set_data`, failing with `ChunkTooLarge` and leaving the chunk as it was when the
+    /// data is longer than `MAX_DATA_LENGTH`
+    pub fn try_set_data(&mut self, data: Vec<u8>) -> Result<(), PngError> {
+        if data_length(data.len()).is_none() {
+            return Err(PngError::ChunkTooLarge(data.len()));
+        }
+        self.set_data(data);
+        Ok(())
+    }
+    /// Gives mutable access to the data, length and CRC are recomputed when the guard is dropped
+    ///
+    /// # Panics
+    /// When the guard is dropped with more than `MAX_DATA_LENGTH` bytes of data
+    pub fn data_mut(&mut self) -> DataMut<'_> {
+        DataMut { chunk: self }
+    }
+    /// Changes the chunk type, recomputing the CRC
+    pub fn set_chunk_type(&mut self, chunk_type: ChunkType) {
+        self.chunk_type = chunk_type;
+        self.update();
+    }
+    fn update(&mut self) {
+        self.data_length = data_length(self.data.len())
+            .unwrap_or_else(|| panic!("{} bytes of chunk data exceed MAX_DATA_LENGTH", self.data.len()));
+        self.crc = self.computed_crc();
+    }
     pub fn data_as_string(&self) -> Result<String> {
         let res = String::from_utf8(self.data.clone())?;
         Ok(res)
     }
+    /// Parses a chunk without checking its CRC, keeping the stored CRC as it is
+    pub fn try_from_unchecked(value: &[u8]) -> Result<Chunk, PngError> {
+        ChunkRef::try_from_unchecked(value).map(|chunk| chunk.to_chunk())
+    }
+    /// Borrowed view of the chunk, the form read-only code takes chunks in
+    pub fn as_chunk_ref(&self) -> ChunkRef<'_> {
+        ChunkRef {
+            data_length: self.data_length,
+            chunk_type: self.chunk_type.clone(),
+            data: &self.data,
+            crc: self.crc,
+        }
+    }
     pub fn as_bytes(&self) -> Vec<u8> {
         self.data_length.to_be_bytes().iter()
             .chain(self.chunk_type.to_string().as_bytes())
@@ -55,28 +121,123 @@ impl Chunk {
     }
 }
 
+// the length field for `len` bytes of data, None past what a chunk can hold
+fn data_length(len: usize) -> Option<u32> {
+    match len <= MAX_DATA_LENGTH {
+        true => Some(len as u32),
+        false => None,
+    }
+}
+
+/// Mutable view of a chunk's data returned by `Chunk::data_mut`
+pub struct DataMut<'a> {
+    chunk: &'a mut Chunk,
+}
+
+impl Deref for DataMut<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.chunk.data
+    }
+}
+
+impl DerefMut for DataMut<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.chunk.data
+    }
+}
+
+impl Drop for DataMut<'_> {
+    fn drop(&mut self) {
+        self.chunk.update();
+    }
+}
+
 impl TryFrom<&[u8]> for Chunk {
     type Error = PngError;
 
     fn try_from(value: &[u8]) -> Result<Self, PngError> {
-        let len = &value[0..4];
+        ChunkRef::try_from(value).map(|chunk| chunk.to_chunk())
+    }
+}
+
+/// A chunk whose data borrows from the bytes it was parsed from, such as a memory mapped file
+#[derive(Debug, Clone)]
+pub struct ChunkRef<'a> {
+    data_length: u32,
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses a chunk without checking its CRC, keeping the stored CRC as it is
+    pub fn try_from_unchecked(value: &'a [u8]) -> Result<ChunkRef<'a>, PngError> {
+        if value.len() < 12 {
+            return Err(PngError::ChunkError);
+        }
         let crc = &value[value.len() - 4..];
-        return match value.len() / 12 {
+        Ok(ChunkRef {
+            data_length: read_be_u32(&value[0..4]),
+            chunk_type: ChunkType::try_from(<[u8; 4]>::try_from(&value[4..8]).unwrap())?,
+            data: &value[8..value.len() - 4],
+            crc: read_be_u32(crc),
+        })
+    }
+    pub fn length(&self) -> u32 {
+        self.data_length
+    }
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+    /// CRC of the chunk type and data, which `crc` may not match for chunks parsed without
+    /// checking their CRC
+    pub fn computed_crc(&self) -> u32 {
+        let mut digest = CRC_32_ISO.digest();
+        digest.update(&self.chunk_type.bytes());
+        digest.update(self.data);
+        digest.finalize()
+    }
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+    /// The data as text, without copying it
+    pub fn data_as_str(&self) -> Result<&'a str, PngError> {
+        std::str::from_utf8(self.data).map_err(|_| PngError::ChunkError)
+    }
+    /// Copies the data into an owned chunk, keeping the stored length and CRC
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            data_length: self.data_length,
+            chunk_type: self.chunk_type.clone(),
+            data: self.data.to_vec(),
+            crc: self.crc,
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, PngError> {
+        match value.len() / 12 {
             0 => Err(PngError::ChunkError),
             _ => {
                 // check CRC
+                let crc = &value[value.len() - 4..];
                 if read_be_u32(crc) != CRC_32_ISO.checksum(&value[4..value.len() - 4]) {
                     return Err(PngError::CRCError);
                 }
-                Ok(Chunk {
-                    data_length: read_be_u32(len),
-                    chunk_type: ChunkType::try_from(<[u8; 4]>::try_from(&value[4..8]).unwrap()).unwrap(),
-                    data: value[8..value.len() - 4].to_vec(),
-                    crc: read_be_u32(crc),
-
-                })
+                ChunkRef::try_from_unchecked(value)
             }
-        };
+        }
     }
 }
 
@@ -97,6 +258,51 @@ fn read_be_u32(input: &[u8]) -> u32 {
     u32::from_be_bytes(int_bytes.try_into().unwrap())
 }
 
+// serialized form of a chunk, the data as hex
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChunkFields {
+    #[serde(rename = "type")]
+    chunk_type: ChunkType,
+    length: u32,
+    data: String,
+    crc: u32,
+}
+
+/// Serialized as its type, length, hex encoded data and CRC. Deserializing checks the length
+/// and CRC against the data, so chunks parsed with a bad CRC do not round-trip.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Chunk {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = ChunkFields {
+            chunk_type: self.chunk_type.clone(),
+            length: self.data_length,
+            data: hex::encode(&self.data),
+            crc: self.crc,
+        };
+        serde::Serialize::serialize(&fields, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Chunk {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let fields = ChunkFields::deserialize(deserializer)?;
+        let data = hex::decode(&fields.data).map_err(|err| D::Error::custom(format!("invalid chunk data: {}", err)))?;
+        if data.len() != fields.length as usize || data.len() > MAX_DATA_LENGTH {
+            return Err(D::Error::custom(format!("length {} does not match {} bytes of data", fields.length, data.len())));
+        }
+        let chunk = Chunk::new(fields.chunk_type, data);
+        if chunk.crc != fields.crc {
+            return Err(D::Error::custom(format!("crc {} does not match the computed crc {}", fields.crc, chunk.crc)));
+        }
+        Ok(chunk)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,6 +362,15 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_chunk_has_valid_crc() {
+        let chunk = testing_chunk();
+        assert!(chunk.has_valid_crc());
+        let mut bytes = chunk.as_bytes();
+        bytes[10] ^= 0xff;
+        assert!(!Chunk::try_from_unchecked(&bytes).unwrap().has_valid_crc());
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -204,6 +419,29 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_unchecked_chunk_from_bytes() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656333;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let chunk = Chunk::try_from_unchecked(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.crc(), 2882656333);
+        assert_eq!(chunk.data_as_string().unwrap(), "This is where your secret message will be!");
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -224,4 +462,65 @@ mod tests {
         
         let _chunk_string = format!("{}", chunk);
     }
+
+    #[test]
+    pub fn test_chunk_ref() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        let borrowed = ChunkRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(borrowed.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(borrowed.length(), 42);
+        assert_eq!(borrowed.data_as_str().unwrap(), "This is where your secret message will be!");
+        assert_eq!(borrowed.computed_crc(), chunk.crc());
+        assert_eq!(borrowed.to_chunk().as_bytes(), bytes);
+        assert_eq!(chunk.as_chunk_ref().data(), chunk.data());
+
+        let mut damaged = bytes.clone();
+        damaged[10] ^= 1;
+        assert!(matches!(ChunkRef::try_from(damaged.as_slice()), Err(PngError::CRCError)));
+        assert!(!ChunkRef::try_from_unchecked(&damaged).unwrap().has_valid_crc());
+    }
+
+    #[test]
+    pub fn test_chunk_mutation() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"short".to_vec());
+        assert_eq!(chunk.length(), 5);
+        assert!(chunk.has_valid_crc());
+
+        chunk.data_mut().extend_from_slice(b" and longer");
+        assert_eq!(chunk.length(), 16);
+        assert_eq!(chunk.data(), b"short and longer");
+        assert!(chunk.has_valid_crc());
+
+        chunk.set_chunk_type(ChunkType::from_str("ruSt").unwrap());
+        assert!(chunk.has_valid_crc());
+        let expected = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"short and longer".to_vec());
+        assert_eq!(chunk.as_bytes(), expected.as_bytes());
+
+        assert!(chunk.try_set_data(b"fits".to_vec()).is_ok());
+        assert_eq!(chunk.length(), 4);
+    }
+
+    #[test]
+    fn test_data_length_limit() {
+        assert_eq!(data_length(MAX_DATA_LENGTH), Some(MAX_DATA_LENGTH as u32));
+        // past 2^31 - 1 the length field would no longer match the data
+        assert_eq!(data_length(MAX_DATA_LENGTH + 1), None);
+        assert_eq!(data_length(usize::MAX), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_chunk_serde() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"hi".to_vec());
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(json, format!(r#"{{"type":"RuSt","length":2,"data":"6869","crc":{}}}"#, chunk.crc()));
+        assert_eq!(serde_json::from_str::<Chunk>(&json).unwrap().as_bytes(), chunk.as_bytes());
+
+        let bad_crc = json.replace(&chunk.crc().to_string(), "1");
+        assert!(serde_json::from_str::<Chunk>(&bad_crc).is_err());
+        let bad_length = json.replace(r#""length":2"#, r#""length":3"#);
+        assert!(serde_json::from_str::<Chunk>(&bad_length).is_err());
+    }
 }
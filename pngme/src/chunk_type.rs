@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
 use anyhow::Result;
@@ -13,50 +15,55 @@ fn is_public(&self) -> bool
 fn is_reserved_bit_valid(&self) -> bool
 fn is_safe_to_copy(&self) -> bool
  */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChunkType(u8, u8, u8, u8);
 
+/// Positions of the chunks of each type in a chunk list
+pub type TypeIndex = HashMap<ChunkType, Vec<usize>>;
+
+/// Builds the index of a list of chunk types
+pub fn index_types<'a, I: IntoIterator<Item = &'a ChunkType>>(types: I) -> TypeIndex {
+    let mut res = TypeIndex::new();
+    for (i, chunk_type) in types.into_iter().enumerate() {
+        res.entry(chunk_type.clone()).or_default().push(i);
+    }
+    res
+}
+
 impl ChunkType {
-    fn bytes(&self) -> [u8; 4] {
+    pub fn bytes(&self) -> [u8; 4] {
         [self.0, self.1, self.2, self.3]
     }
 
     // bit 5 of first byte
     // 0-uppercase-critical
     // 1-lowercase-ancillary
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         self.0.is_ascii_uppercase()
     }
 
     // bit 5 of second byte
     // 0-uppercase-public
     // 1-lowercase-private
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         self.1.is_ascii_uppercase()
     }
 
     // bit 5 of third byte
     // must be 0-uppercase
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         self.2.is_ascii_uppercase()
     }
 
     // bit 5 of second byte
     // 0-uppercase-unsafe to copy
     // 1-lowercase-safe to copy
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         self.3.is_ascii_lowercase()
     }
-    fn is_valid(&self) -> bool {
+    pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
-
-    fn is_err(&self) -> bool {
-        !(self.0.is_ascii_alphabetic()
-            && self.1.is_ascii_alphabetic()
-            && self.2.is_ascii_alphabetic()
-            && self.3.is_ascii_alphabetic())
-    }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
@@ -78,7 +85,8 @@ impl FromStr for ChunkType {
 
     fn from_str(s: &str) -> std::result::Result<Self, PngError> {
         let value = s.as_bytes();
-        ChunkType::try_from(<[u8; 4]>::try_from(value).unwrap())
+        let bytes = <[u8; 4]>::try_from(value).map_err(|_| PngError::ChunkTypeError)?;
+        ChunkType::try_from(bytes)
     }
 }
 
@@ -91,12 +99,38 @@ impl PartialEq for ChunkType {
     }
 }
 
+impl Eq for ChunkType {}
+
+impl Hash for ChunkType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes().hash(state);
+    }
+}
+
 impl Display for ChunkType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let s = String::from_utf8(self.bytes().to_vec()).unwrap();
         write!(f, "{}", s)
     }
-}#[cfg(test)]
+}
+
+/// Serialized as its four character string
+#[cfg(feature = "serde")]
+impl serde::Serialize for ChunkType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ChunkType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        ChunkType::from_str(&s).map_err(|_| serde::de::Error::custom(format!("invalid chunk type {:?}", s)))
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::convert::TryFrom;
@@ -178,6 +212,9 @@ mod tests {
 
         let chunk = ChunkType::from_str("Ru1t");
         assert!(chunk.is_err());
+
+        let chunk = ChunkType::from_str("RuSty");
+        assert!(chunk.is_err());
     }
 
     #[test]
@@ -193,4 +230,13 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_chunk_type_serde() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(serde_json::to_string(&chunk_type).unwrap(), "\"RuSt\"");
+        assert_eq!(serde_json::from_str::<ChunkType>("\"RuSt\"").unwrap(), chunk_type);
+        assert!(serde_json::from_str::<ChunkType>("\"Ru1t\"").is_err());
+    }
 }
@@ -1,66 +1,942 @@
-use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
-use crate::png::Png;
-use crate::chunk::Chunk;
+use crate::append;
+use crate::atomic;
+use crate::args::{
+    ColorChoice, CompressArg, DecodeArgs, DiffArgs, DumpArgs, EncodeArgs, InspectArgs, KeygenArgs, ManifestArg,
+    MessagesArgs, OutputArgs, OutputFormat, PackArgs, PrintArgs, RemoveArgs, RepairArgs, ScanArgs, SignArgs, StripArgs,
+    TransplantArgs, UnpackArgs, UpdateArgs, ValidateArgs, VerifyArgs,
+};
+use crate::batch::{self, Inputs};
+use crate::pixels::{self, PixelComparison};
+use crate::png::{AncillaryCopy, Png, PngRef};
+use crate::chunk::{Chunk, MAX_DATA_LENGTH};
 use crate::chunk_type::ChunkType;
+use crate::compression::{self, Codec};
+use crate::crypto::{self, Identity, PublicKey};
+use crate::diff;
+use crate::fec;
+use crate::frame::{self, Frame, FRAME_LEN};
+use crate::hexdump;
+use crate::manifest::{self, ManifestFormat};
+use crate::policy;
+use crate::reader::{ChunkReader, ReadSeek};
 use crate::png_error::PngError;
+use crate::records::{
+    self, ChunkRecord, DecodeRecord, MessageRecord, PayloadKind, PrintRecord, RemoveRecord, ScanHit, ScanRecord,
+    StripRecord, ValidateRecord,
+};
+use crate::repair;
+use crate::signature::{self, SignerKey, SigningIdentity};
+use crate::split;
+use crate::stdio;
+use crate::strip::{self, Preset, StripPolicy};
+use crate::style::{paint, BLUE, BOLD, CYAN, GREEN, MAGENTA, PLAIN, RED, YELLOW};
+use crate::summary;
+use crate::validate;
 
 /// Encodes a message into a PNG file and saves the result
 pub fn encode(args: &EncodeArgs) -> Result<(), PngError> {
+    if args.out_path().is_some() && args.output.out_dir.is_none() && batch::is_batch(&args.file_path) {
+        return Err(PngError::BatchOutput);
+    }
+    let message = read_message_arg(args.message.as_deref(), args.message_file.as_deref())?;
+    batch::for_each_input(&args.file_path, args.batch.jobs, false, |inputs, input_file, _| {
+        let output_path = match args.in_place {
+            true => input_file.to_path_buf(),
+            false => output_path(inputs, input_file, args.out_path(), &args.output)?
+                .ok_or(PngError::MissingOutput)?,
+        };
+        encode_file(args, &message, input_file, &output_path)
+    })
+}
+
+// the message given as text, or read from the message file or else stdin
+fn read_message_arg(message: Option<&str>, message_file: Option<&Path>) -> Result<Vec<u8>, PngError> {
+    match (message, message_file) {
+        (Some(message), _) => Ok(message.as_bytes().to_vec()),
+        (None, Some(message_file)) => stdio::read(message_file),
+        // waiting on a terminal would look like a hang
+        (None, None) if io::stdin().is_terminal() => Err(PngError::InputError(
+            "no message given, use --message, --message-file or pipe it to stdin".to_string(),
+        )),
+        (None, None) => stdio::read(Path::new(stdio::STDIO_PATH)),
+    }
+}
+
+fn encode_file(args: &EncodeArgs, message: &[u8], input_file: &Path, output_path: &Path) -> Result<(), PngError> {
     // get basic info
-    let input_file = &args.file_path;
     let chunk_type = ChunkType::from_str(args.chunk_type.as_str())?;
-    let chunk_data = args.message.clone().into_bytes();
-    let message_chunk = Chunk::new(chunk_type, chunk_data);
+    if !args.force {
+        policy::check_encode(&chunk_type)?;
+    }
+    let (frame, payload) = encode_payload(message, args.compress, &args.recipients, args.ecc)?;
+    let pieces = frame_payload(frame, &payload, args.split)?;
+    // appending in place leaves the image data where it is instead of copying the whole file
+    if args.in_place && !args.backup.keep && !stdio::is_stdio(input_file) {
+        let chunks: Vec<Chunk> = pieces.into_iter().map(|piece| Chunk::new(chunk_type.clone(), piece)).collect();
+        return append::append_chunks(input_file, &chunks);
+    }
     // create Png from file path
-    let mut png = Png::from_file(input_file)?;
-    // add secret message chunk
-    png.append_chunk(message_chunk);
+    let mut png = read_png(input_file)?;
+    // add secret message chunks just before IEND, where append_chunks puts them too
+    png.insert_before_end(pieces.into_iter().map(|piece| Chunk::new(chunk_type.clone(), piece)))?;
     // output new file path
-    if let Some(output_path) = &args.out_path {
-        fs::write(output_path, png.as_bytes())?;
+    stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    Ok(())
+}
+
+// the message compressed, then sealed, then with parity added, and the frame recording which
+// of these were applied
+fn encode_payload(
+    message: &[u8],
+    compress: Option<CompressArg>,
+    recipients: &[String],
+    ecc: Option<u8>,
+) -> Result<(Frame, Vec<u8>), PngError> {
+    let mut frame = Frame::default();
+    let mut chunk_data = message.to_vec();
+    // compress before sealing, ciphertext does not compress
+    let compressed = match compress {
+        None => None,
+        Some(CompressArg::Auto) => compression::compress_smallest(&chunk_data)?,
+        Some(CompressArg::Deflate) => Some(compression::compress(&chunk_data, Codec::Deflate)?),
+        Some(CompressArg::Zstd) => Some(compression::compress(&chunk_data, Codec::Zstd)?),
+    };
+    if let Some(compressed) = compressed {
+        chunk_data = compressed;
+        frame.compressed = true;
+    }
+    // seal the message for the given recipients
+    if !recipients.is_empty() {
+        let recipients = recipients.iter()
+            .map(|arg| PublicKey::from_arg(arg))
+            .collect::<Result<Vec<PublicKey>, PngError>>()?;
+        chunk_data = crypto::seal(&chunk_data, &recipients)?;
+        frame.sealed = true;
+    }
+    // add parity after sealing so damage to the ciphertext can be repaired
+    if let Some(parity) = ecc {
+        chunk_data = fec::encode(&chunk_data, parity)?;
+        frame.error_corrected = true;
+    }
+    Ok((frame, chunk_data))
+}
+
+// the data of the chunks holding a payload: one chunk, framed when an encoding was applied,
+// or framed pieces of at most `max_chunk_size` bytes each when splitting
+fn frame_payload(frame: Frame, payload: &[u8], max_chunk_size: Option<usize>) -> Result<Vec<Vec<u8>>, PngError> {
+    let max_chunk_size = match max_chunk_size {
+        None => {
+            let data = frame.wrap(payload);
+            return match data.len() > MAX_DATA_LENGTH {
+                true => Err(PngError::ChunkTooLarge(data.len())),
+                false => Ok(vec![data]),
+            };
+        }
+        Some(max_chunk_size) if max_chunk_size > MAX_DATA_LENGTH => {
+            return Err(PngError::PieceSizeError(max_chunk_size))
+        }
+        Some(max_chunk_size) => max_chunk_size,
+    };
+    // every piece starts with its own frame byte
+    let frame = Frame { split: true, ..frame };
+    let pieces = split::split(payload, max_chunk_size.saturating_sub(FRAME_LEN))
+        .map_err(|_| PngError::PieceSizeError(max_chunk_size))?;
+    Ok(pieces.iter().map(|piece| frame.wrap(piece)).collect())
+}
+
+/// Replaces a message hidden in a PNG file, keeping the chunk where it is
+pub fn update(args: &UpdateArgs) -> Result<(), PngError> {
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    if !args.force {
+        policy::check_encode(&chunk_type)?;
+    }
+    let message = read_message_arg(args.message.message.as_deref(), args.message.message_file.as_deref())?;
+    let (frame, payload) = encode_payload(&message, args.compress, &args.recipients, args.ecc)?;
+    let chunk_data = frame_payload(frame, &payload, None)?.remove(0);
+    let mut png = read_png(&args.file_path)?;
+    let old = png.chunks_by_type(&args.chunk_type)
+        .get(args.index)
+        .map(|chunk| chunk.data().to_vec())
+        .ok_or(PngError::NotFoundChunkType)?;
+    // a single piece cannot be swapped without breaking the rest of its message
+    if frame::piece(&old).is_some() {
+        return Err(PngError::InputError(
+            "the message is split over several chunks, use remove and encode --split instead".to_string(),
+        ));
+    }
+    let length = chunk_data.len();
+    png.replace_chunk_data(&args.chunk_type, args.index, chunk_data)?;
+    let output_path = args.out_path.as_ref().unwrap_or(&args.file_path);
+    stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    if !stdio::is_stdio(output_path) {
+        println!("updated {} #{}: {} bytes, was {}", args.chunk_type, args.index, length, old.len());
     }
     Ok(())
 }
 
 /// Searches for a message hidden in a PNG file and prints the message if one is found
 pub fn decode(args: &DecodeArgs) -> Result<(), PngError> {
-    let input_file = &args.file_path;
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |_, input_file, out| decode_file(args, input_file, out))
+}
+
+fn decode_file(args: &DecodeArgs, input_file: &Path, out: &mut dyn Write) -> Result<(), PngError> {
     let chunk_type = args.chunk_type.as_str();
-    let png = Png::from_file(input_file)?;
-    let message = png.data_string_by_type(chunk_type);
-    match message {
-        None => { println!("no such message for chunk_type: {}.", chunk_type) }
-        Some(msg) => {
-            println!("secret msg for {} is: {}", chunk_type, msg)
+    // only the chunk headers and the chunks holding the message are read
+    let mut reader = ChunkReader::open(input_file)?;
+    let positions = reader.indices_of(&ChunkType::from_str(chunk_type)?).to_vec();
+    let selected: Vec<usize> = match (args.all, args.offset, args.index) {
+        (true, _, _) => (0..positions.len()).collect(),
+        (false, Some(offset), _) => {
+            let index = reader.position_at(offset)
+                .and_then(|position| positions.iter().position(|p| *p == position))
+                .ok_or_else(|| PngError::InputError(format!("no {} chunk starts at offset {}", chunk_type, offset)))?;
+            vec![index]
+        }
+        (false, None, index) => {
+            let index = index.unwrap_or(0);
+            (index < positions.len()).then_some(index).into_iter().collect()
+        }
+    };
+    let chunks = selected.iter()
+        .map(|index| read_chunk(&mut reader, positions[*index], args.relaxed_crc))
+        .collect::<Result<Vec<Chunk>, PngError>>()?;
+    // the other pieces of a split message are only read when one of them was selected
+    let pieces = match !args.all && chunks.iter().any(|chunk| frame::piece(chunk.data()).is_some()) {
+        true => positions.iter()
+            .map(|position| read_chunk(&mut reader, *position, args.relaxed_crc))
+            .collect::<Result<Vec<Chunk>, PngError>>()?,
+        false => Vec::new(),
+    };
+    let same_type: Vec<&Chunk> = match args.all {
+        true => chunks.iter().collect(),
+        false => pieces.iter().collect(),
+    };
+    let messages: Vec<(usize, &Chunk)> = selected.into_iter()
+        .zip(chunks.iter())
+        .filter(|(_, chunk)| !(args.all && frame::piece(chunk.data()).is_some_and(split::is_continuation)))
+        .collect();
+    if args.format.is_json() {
+        let messages = messages.into_iter()
+            .map(|(index, chunk)| {
+                let (data, corrected) = read_message(chunk, &same_type, args)?;
+                let offset = reader.entries()[positions[index]].offset as usize;
+                Ok(MessageRecord::new(index, offset, chunk, &data, corrected))
+            })
+            .collect::<Result<Vec<MessageRecord>, PngError>>()?;
+        return records::write_json(out, &DecodeRecord { file: input_file, chunk_type, messages });
+    }
+    if messages.is_empty() {
+        writeln!(out, "no such message for chunk_type: {}.", chunk_type)?;
+        return Ok(());
+    }
+    for (index, chunk) in messages {
+        let (data, corrected) = read_message(chunk, &same_type, args)?;
+        if corrected > 0 {
+            writeln!(out, "corrected {} damaged bytes", corrected)?;
+        }
+        match args.all {
+            true => writeln!(out, "secret msg {} for {} is: {}", index, chunk_type, String::from_utf8_lossy(&data))?,
+            false => writeln!(out, "secret msg for {} is: {}", chunk_type, String::from_utf8_lossy(&data))?,
         }
     }
     Ok(())
 }
 
+// a damaged message chunk may still be repaired by error correction when CRCs are relaxed
+fn read_chunk(reader: &mut ChunkReader<Box<dyn ReadSeek>>, position: usize, relaxed_crc: bool) -> Result<Chunk, PngError> {
+    match relaxed_crc {
+        true => reader.read_chunk_unchecked(position),
+        false => reader.read_chunk(position),
+    }
+}
+
+// undo every encoding step recorded in the frame, in reverse order, returning the message and
+// how many bytes error correction repaired
+fn read_message(chunk: &Chunk, same_type: &[&Chunk], args: &DecodeArgs) -> Result<(Vec<u8>, usize), PngError> {
+    // data without a frame was not written by pngme and is shown as it is
+    let (frame, body) = match frame::read(chunk.data()) {
+        Some(framed) => framed,
+        None => return Ok((chunk.data().to_vec(), 0)),
+    };
+    let mut data = body.to_vec();
+    let mut corrected = 0;
+    if frame.split {
+        // the selected piece picks which message is reassembled
+        let first = frame::piece(chunk.data()).ok_or(PngError::PieceError)?;
+        let pieces = same_type.iter().filter_map(|chunk| frame::piece(chunk.data()));
+        data = split::reassemble(first, pieces)?;
+    }
+    if frame.error_corrected {
+        let recovered = fec::decode(&data)?;
+        corrected = recovered.corrected;
+        data = recovered.data;
+    }
+    if frame.sealed {
+        let identity_path = args.identity.as_ref().ok_or(PngError::EncryptedMessage)?;
+        let identity = Identity::from_file(identity_path)?;
+        data = crypto::open(&data, &identity)?;
+    }
+    if frame.compressed {
+        data = compression::decompress(&data, args.max_output)?;
+    }
+    Ok((data, corrected))
+}
+
 /// Removes a chunk from a PNG file and saves the result
 pub fn remove(args: &RemoveArgs) -> Result<(), PngError> {
-    let input_file = &args.file_path;
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |inputs, input_file, out| {
+        let output_path = output_path(inputs, input_file, None, &args.output)?
+            .unwrap_or_else(|| input_file.to_path_buf());
+        // keep stdout for the image when it is written there
+        match stdio::is_stdio(&output_path) {
+            true => remove_file(args, input_file, &output_path, &mut io::stderr()),
+            false => remove_file(args, input_file, &output_path, out),
+        }
+    })
+}
+
+fn remove_file(
+    args: &RemoveArgs,
+    input_file: &Path,
+    output_path: &Path,
+    out: &mut dyn Write,
+) -> Result<(), PngError> {
     let chunk_type = args.chunk_type.as_str();
-    let mut png = Png::from_file(input_file)?;
-    let chunk_removed = png.remove_chunk(chunk_type)?;
-    fs::write(input_file, png.as_bytes())?;
-    println!("remove chunk type: {}", chunk_type);
+    if !args.force {
+        policy::check_remove(&ChunkType::from_str(chunk_type)?)?;
+    }
+    let mut png = read_png(input_file)?;
+    // describe the chunks before they are gone
+    let records = chunk_records(&png.as_png_ref());
+    let positions = png.indices_of(&ChunkType::from_str(chunk_type)?).to_vec();
+    let selected = match args.all {
+        true => positions,
+        false => {
+            let position = *positions.get(args.index.unwrap_or(0)).ok_or(PngError::NotFoundChunkType)?;
+            // a piece of a split message goes along with the other pieces, which could not be
+            // decoded without it
+            let message_id = |position: &usize| frame::piece(png.chunks()[*position].data()).map(split::message_id);
+            match message_id(&position) {
+                Some(id) => positions.iter().copied().filter(|p| message_id(p) == Some(id)).collect(),
+                None => vec![position],
+            }
+        }
+    };
+    if selected.is_empty() {
+        return Err(PngError::NotFoundChunkType);
+    }
+    // positions come sorted from the type index
+    png.drain_where(|position, _| selected.binary_search(&position).is_ok());
+    let removed: Vec<ChunkRecord> = records.into_iter()
+        .filter(|record| selected.binary_search(&record.index).is_ok())
+        .collect();
+    stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    match args.format.format {
+        OutputFormat::Json => {
+            let record = RemoveRecord { file: input_file, output: output_path, chunk_type, removed };
+            records::write_json(out, &record)?;
+        }
+        OutputFormat::Text => writeln!(out, "remove chunk type: {} ({} removed)", chunk_type, removed.len())?,
+    }
+    Ok(())
+}
+
+/// Removes metadata chunks chosen by presets, chunk types and a policy file, and any data
+/// after IEND, then saves the result
+pub fn strip(args: &StripArgs) -> Result<(), PngError> {
+    let presets = [
+        (args.all_ancillary, Preset::AllAncillary),
+        (args.private_only, Preset::PrivateOnly),
+        (args.text, Preset::Text),
+        (args.exif, Preset::Exif),
+    ];
+    let presets = presets.iter().filter(|(chosen, _)| *chosen).map(|(_, preset)| *preset).collect();
+    let mut policy = StripPolicy::new(presets, &args.keep, &args.remove)?;
+    if let Some(policy_path) = &args.policy {
+        policy.merge(StripPolicy::from_file(policy_path)?);
+    }
+    if policy.is_empty() {
+        policy.presets.push(Preset::AllAncillary);
+    }
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |inputs, input_file, out| {
+        let output_path = output_path(inputs, input_file, None, &args.output)?
+            .unwrap_or_else(|| input_file.to_path_buf());
+        // keep stdout for the image when it is written there
+        match stdio::is_stdio(&output_path) {
+            true => strip_file(args, &policy, input_file, &output_path, &mut io::stderr()),
+            false => strip_file(args, &policy, input_file, &output_path, out),
+        }
+    })
+}
+
+fn strip_file(
+    args: &StripArgs,
+    policy: &StripPolicy,
+    input_file: &Path,
+    output_path: &Path,
+    out: &mut dyn Write,
+) -> Result<(), PngError> {
+    let bytes = stdio::read(input_file)?;
+    let (mut png, trailing) = Png::try_from_until_end(&bytes)?;
+    // describe the chunks before they are gone
+    let records = chunk_records(&png.as_png_ref());
+    let removed: Vec<usize> = strip::strip(&mut png, policy).into_iter().map(|(index, _)| index).collect();
+    stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    let removed: Vec<ChunkRecord> = records.into_iter()
+        .filter(|record| removed.contains(&record.index))
+        .collect();
+    match args.format.format {
+        OutputFormat::Json => {
+            let record = StripRecord { file: input_file, output: output_path, removed, trailing_bytes: trailing.len() };
+            records::write_json(out, &record)?;
+        }
+        OutputFormat::Text => {
+            for record in removed.iter() {
+                writeln!(out, "removed {} at offset {}: {} bytes", record.chunk_type, record.offset, record.length)?;
+            }
+            if !trailing.is_empty() {
+                writeln!(out, "removed {} bytes after IEND", trailing.len())?;
+            }
+            writeln!(out, "{} chunks removed, {} kept", removed.len(), png.chunks().len())?;
+        }
+    }
     Ok(())
 }
 
 /// Prints all of the chunks in a PNG file
 pub fn print_chunks(args: &PrintArgs) -> Result<(), PngError> {
-    let input_file = &args.file_path;
-    let mut png = Png::from_file(input_file)?;
-    let v = &png.chunks;
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |_, input_file, out| print_file(args, input_file, out))
+}
+
+fn print_file(args: &PrintArgs, input_file: &Path, out: &mut dyn Write) -> Result<(), PngError> {
+    let bytes = stdio::map(input_file)?;
+    let png = PngRef::try_from(&bytes[..])?;
+    if args.format.is_json() {
+        return records::write_json(out, &PrintRecord { file: input_file, chunks: chunk_records(&png) });
+    }
+    let v = png.chunks();
     let num = v.len();
-    println!("====================all chunk type({num})====================");
-    for i in 0..num {
-        print!("{}:{};", i + 1, v[i].chunk_type().to_string());
+    writeln!(out, "====================all chunk type({num})====================")?;
+    for (i, chunk) in v.iter().enumerate() {
+        write!(out, "{}:{};", i + 1, chunk.chunk_type())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Prints a table of every chunk with its offset, flags, stored and computed CRC and a summary
+/// of its contents, followed by any structural problems
+pub fn inspect(args: &InspectArgs) -> Result<(), PngError> {
+    let color = use_color(args.color);
+    batch::for_each_input(&args.file_path, args.batch.jobs, false, |_, input_file, out| inspect_file(input_file, color, out))
+}
+
+fn inspect_file(input_file: &Path, color: bool, out: &mut dyn Write) -> Result<(), PngError> {
+    let bytes = stdio::map(input_file)?;
+    let validation = validate::validate(&bytes);
+    let header = format!(
+        "{:>10} {:>10}  {:<4}  {:<25} {:>8} {:>8}  {}",
+        "offset", "length", "type", "flags", "crc", "computed", "summary"
+    );
+    writeln!(out, "{}", paint(&header, BOLD, color))?;
+    for (offset, chunk) in validation.chunks.iter() {
+        let chunk_type = chunk.chunk_type();
+        let mut flags = vec![
+            if chunk_type.is_critical() { "crit" } else { "anc" },
+            if chunk_type.is_public() { "pub" } else { "priv" },
+            if chunk_type.is_safe_to_copy() { "safe" } else { "unsafe" },
+        ];
+        if !chunk_type.is_reserved_bit_valid() {
+            flags.push("reserved");
+        }
+        let row = format!(
+            "{:>10} {:>10}  {:<4}  {:<25} {:08x} {:08x}  {}",
+            offset,
+            chunk.data().len(),
+            chunk_type,
+            flags.join(" "),
+            chunk.crc(),
+            chunk.computed_crc(),
+            summary::summarize(chunk)
+        );
+        // bad CRCs stand out most, then chunks that may hide a message
+        let style = if !chunk.has_valid_crc() || !chunk_type.is_reserved_bit_valid() {
+            RED
+        } else if !chunk_type.is_public() {
+            YELLOW
+        } else if chunk_type.is_critical() {
+            CYAN
+        } else {
+            PLAIN
+        };
+        writeln!(out, "{}", paint(&row, style, color))?;
+    }
+    for problem in validation.problems.iter() {
+        let line = match problem.offset {
+            Some(offset) => format!("offset {}: {}", offset, problem.message),
+            None => problem.message.clone(),
+        };
+        writeln!(out, "{}", paint(&line, RED, color))?;
+    }
+    Ok(())
+}
+
+/// Prints one chunk as an annotated hex dump, or its data as it is with --raw
+pub fn dump(args: &DumpArgs) -> Result<(), PngError> {
+    let file = stdio::map(&args.file_path)?;
+    let validation = validate::validate(&file);
+    let found = match (&args.chunk_type, args.offset) {
+        (_, Some(at)) => validation.chunks.iter()
+            .find(|(offset, chunk)| (*offset..offset + chunk.data().len() + 12).contains(&at)),
+        (Some(chunk_type), None) => validation.chunks.iter()
+            .filter(|(_, chunk)| chunk.chunk_type().to_string() == *chunk_type)
+            .nth(args.index),
+        (None, None) => None,
+    };
+    let (offset, chunk) = found.ok_or(PngError::NotFoundChunkType)?;
+    let range = match &args.range {
+        Some(range) => hexdump::parse_range(range, chunk.data().len())?,
+        None => 0..chunk.data().len(),
+    };
+    if args.raw {
+        return stdio::write(Path::new(stdio::STDIO_PATH), &chunk.data()[range], false);
+    }
+
+    let color = use_color(args.color);
+    let data_start = offset + 8;
+    let crc_start = data_start + chunk.data().len();
+    let crc_style = if chunk.has_valid_crc() { MAGENTA } else { RED };
+    let fields = [
+        ("length", *offset..offset + 4, BLUE),
+        ("type", offset + 4..data_start, GREEN),
+        ("data", data_start..crc_start, PLAIN),
+        ("crc", crc_start..crc_start + 4, crc_style),
+    ];
+    let mut out = io::stdout().lock();
+    let crc_status = match chunk.has_valid_crc() {
+        true => "ok".to_string(),
+        false => format!("computed {:08x}", chunk.computed_crc()),
+    };
+    writeln!(
+        out,
+        "{} at offset {} ({:#x}): {} bytes of data, CRC {:08x} ({})",
+        chunk.chunk_type(),
+        offset,
+        offset,
+        chunk.data().len(),
+        chunk.crc(),
+        crc_status
+    )?;
+    let legend: Vec<String> = fields.iter()
+        .map(|(name, field, style)| paint(&format!("{} {:08x}..{:08x}", name, field.start, field.end), style, color))
+        .collect();
+    writeln!(out, "{}", legend.join(", "))?;
+    // a range only shows data bytes, addresses stay relative to the start of the file
+    let (bytes, start) = match &args.range {
+        Some(_) => (&chunk.data()[range.clone()], data_start + range.start),
+        None => (&file[*offset..crc_start + 4], *offset),
+    };
+    let style_of = |address: usize| {
+        fields.iter()
+            .find(|(_, field, _)| field.contains(&address))
+            .map_or(PLAIN, |(_, _, style)| *style)
+    };
+    hexdump::write_dump(&mut out, bytes, start, style_of, color)
+}
+
+// colour is used on a terminal unless NO_COLOR is set, or when asked for
+fn use_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// Copies the ancillary chunks of one PNG file into another
+pub fn transplant(args: &TransplantArgs) -> Result<(), PngError> {
+    let source = read_png(&args.source_path)?;
+    let mut png = read_png(&args.file_path)?;
+    let outcomes = png.copy_ancillary_from(&source, args.copy_unsafe);
+    let output_path = args.out_path.as_ref().unwrap_or(&args.file_path);
+    if outcomes.iter().any(|(_, outcome)| *outcome == AncillaryCopy::Copied) || output_path != &args.file_path {
+        stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    }
+    // keep stdout for the image when it is written there
+    match stdio::is_stdio(output_path) {
+        true => write_transplant(&outcomes, &mut io::stderr()),
+        false => write_transplant(&outcomes, &mut io::stdout()),
+    }
+}
+
+fn write_transplant(outcomes: &[(ChunkType, AncillaryCopy)], out: &mut dyn Write) -> Result<(), PngError> {
+    for (chunk_type, outcome) in outcomes.iter() {
+        match outcome {
+            AncillaryCopy::Copied => writeln!(out, "copied {}", chunk_type)?,
+            AncillaryCopy::Unsafe => writeln!(out, "skipped {}: unsafe to copy, pass --unsafe to copy it", chunk_type)?,
+            AncillaryCopy::Present => writeln!(out, "skipped {}: already present", chunk_type)?,
+        }
+    }
+    let copied = outcomes.iter().filter(|(_, outcome)| *outcome == AncillaryCopy::Copied).count();
+    writeln!(out, "{} of {} ancillary chunks copied", copied, outcomes.len())?;
+    Ok(())
+}
+
+/// Reports the chunks added, removed, moved and modified between two PNG files, with the
+/// changed fields of known chunk types, and optionally whether their pixels match
+pub fn diff(args: &DiffArgs) -> Result<(), PngError> {
+    let old = read_png(&args.old_path)?;
+    let new = read_png(&args.new_path)?;
+    let diff = diff::diff(&old, &new);
+    println!("--- {}", args.old_path.display());
+    println!("+++ {}", args.new_path.display());
+    for (id, index) in diff.removed.iter() {
+        println!("removed {} at position {}", id, index);
+    }
+    for (id, index) in diff.added.iter() {
+        println!("added {} at position {}", id, index);
+    }
+    for (id, old_index, new_index) in diff.moved.iter() {
+        println!("moved {} from position {} to {}", id, old_index, new_index);
+    }
+    for modified in diff.modified.iter() {
+        match modified.fields.is_empty() {
+            true => println!(
+                "modified {}: {} -> {} bytes, first difference at byte {}",
+                modified.id, modified.old_length, modified.new_length, modified.first_difference
+            ),
+            false => println!("modified {}", modified.id),
+        }
+        for field in modified.fields.iter() {
+            let show = |value: &Option<String>| value.as_deref().map_or("(none)".to_string(), |v| format!("{:?}", v));
+            println!("  {}: {} -> {}", field.name, show(&field.old), show(&field.new));
+        }
+    }
+    if diff.is_empty() {
+        println!("chunks are identical");
+    }
+    if args.pixels {
+        match pixels::compare(&pixels::decode(&old)?, &pixels::decode(&new)?) {
+            PixelComparison::Identical => println!("pixels are identical"),
+            PixelComparison::DifferentSize { old, new } => {
+                println!("image size differs: {}x{} -> {}x{}", old.0, old.1, new.0, new.1)
+            }
+            PixelComparison::Different { differing, total } => {
+                println!("pixels differ: {} of {} pixels changed", differing, total)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the data of every chunk of a PNG file into a directory along with a manifest
+pub fn unpack(args: &UnpackArgs) -> Result<(), PngError> {
+    let bytes = stdio::read(&args.file_path)?;
+    let (png, trailing) = Png::try_from_until_end(&bytes)?;
+    let earlier = manifest::unpacked_files(&args.out_dir)?;
+    if let Some(path) = earlier.first() {
+        if !args.force {
+            return Err(PngError::ManifestError(format!("{} already exists, use --force", path.display())));
+        }
+    }
+    // an earlier unpack of a larger image would leave chunk files the new manifest does not list
+    for path in earlier {
+        fs::remove_file(path)?;
+    }
+    let format = match args.manifest {
+        ManifestArg::Json => ManifestFormat::Json,
+        ManifestArg::Toml => ManifestFormat::Toml,
+    };
+    let manifest = manifest::unpack(&png, trailing, &args.out_dir, format)?;
+    println!("unpacked {} chunks into {}", manifest.chunks.len(), args.out_dir.display());
+    if !trailing.is_empty() {
+        println!("{} bytes after IEND saved to {}", trailing.len(), manifest.trailing.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Rebuilds a PNG file from a directory written by unpack, warning about structural problems
+pub fn pack(args: &PackArgs) -> Result<(), PngError> {
+    let bytes = manifest::pack(&args.dir)?;
+    for problem in validate::validate(&bytes).problems.iter() {
+        eprintln!("warning: {}", problem.message);
+    }
+    stdio::write(&args.out_path, &bytes, args.backup.keep)
+}
+
+/// Checks the structure and CRCs of PNG files, failing if any problem is found
+pub fn validate(args: &ValidateArgs) -> Result<(), PngError> {
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |_, input_file, out| validate_file(args, input_file, out))
+}
+
+fn validate_file(args: &ValidateArgs, input_file: &Path, out: &mut dyn Write) -> Result<(), PngError> {
+    let bytes = stdio::map(input_file)?;
+    let validation = validate::validate(&bytes);
+    match args.format.format {
+        OutputFormat::Json => {
+            let chunks = validation.chunks.iter()
+                .enumerate()
+                .map(|(index, (offset, chunk))| ChunkRecord::new(index, *offset, chunk))
+                .collect();
+            let record = ValidateRecord {
+                file: input_file,
+                valid: validation.is_valid(),
+                chunks,
+                problems: &validation.problems,
+            };
+            records::write_json(out, &record)?;
+        }
+        OutputFormat::Text => {
+            for problem in validation.problems.iter() {
+                match problem.offset {
+                    Some(offset) => writeln!(out, "offset {}: {}", offset, problem.message)?,
+                    None => writeln!(out, "{}", problem.message)?,
+                }
+            }
+            match validation.is_valid() {
+                true => writeln!(out, "{} chunks, valid", validation.chunks.len())?,
+                false => writeln!(out, "{} chunks, {} problems", validation.chunks.len(), validation.problems.len())?,
+            }
+        }
+    }
+    match validation.is_valid() {
+        true => Ok(()),
+        false => Err(PngError::ValidationFailed(validation.problems.len())),
+    }
+}
+
+/// Repairs damaged PNG files, logging every fix and anything left that could not be fixed
+pub fn repair(args: &RepairArgs) -> Result<(), PngError> {
+    batch::for_each_input(&args.file_path, args.batch.jobs, false, |inputs, input_file, out| {
+        let output_path = output_path(inputs, input_file, None, &args.output)?
+            .unwrap_or_else(|| input_file.to_path_buf());
+        // keep stdout for the image when it is written there
+        match stdio::is_stdio(&output_path) && !args.dry_run {
+            true => repair_file(args, input_file, &output_path, &mut io::stderr()),
+            false => repair_file(args, input_file, &output_path, out),
+        }
+    })
+}
+
+fn repair_file(args: &RepairArgs, input_file: &Path, output_path: &Path, out: &mut dyn Write) -> Result<(), PngError> {
+    if !stdio::is_stdio(input_file) {
+        match (args.dry_run, atomic::journal_path(input_file).exists()) {
+            // the rest cannot be told before the file is restored
+            (true, true) => {
+                writeln!(out, "an interrupted append would be rolled back")?;
+                return Ok(());
+            }
+            (false, true) if append::recover(input_file)? => writeln!(out, "rolled back an interrupted append")?,
+            _ => {}
+        }
+    }
+    let repaired = repair::repair(&stdio::read(input_file)?)?;
+    for fix in repaired.fixes.iter() {
+        match fix.offset {
+            Some(offset) => writeln!(out, "offset {}: {}", offset, fix.message)?,
+            None => writeln!(out, "{}", fix.message)?,
+        }
+    }
+    let bytes = repaired.png.as_bytes();
+    for problem in validate::validate(&bytes).problems.iter() {
+        writeln!(out, "not repaired: {}", problem.message)?;
+    }
+    match repaired.fixes.len() {
+        0 => writeln!(out, "nothing to repair")?,
+        fixes => writeln!(out, "{} fixes", fixes)?,
+    }
+    // an intact file is only written when it goes somewhere else
+    if !args.dry_run && (!repaired.fixes.is_empty() || output_path != input_file) {
+        // dropped bytes cannot be told apart from data repair failed to read, keep the original
+        let keep_original = repaired.dropped > 0 && output_path == input_file && !stdio::is_stdio(input_file);
+        if keep_original && !args.backup.keep {
+            let backup = atomic::backup_path(input_file);
+            writeln!(out, "{} bytes dropped, keeping the original in {}", repaired.dropped, backup.display())?;
+        }
+        stdio::write(output_path, &bytes, args.backup.keep || keep_original)?;
     }
     Ok(())
 }
+
+/// Looks for hidden messages in PNG files: private ancillary chunks and anything after IEND
+pub fn scan(args: &ScanArgs) -> Result<(), PngError> {
+    let json = args.format.is_json();
+    batch::for_each_input(&args.file_path, args.batch.jobs, json, |_, input_file, out| scan_file(args, input_file, out))
+}
+
+fn scan_file(args: &ScanArgs, input_file: &Path, out: &mut dyn Write) -> Result<(), PngError> {
+    let bytes = stdio::map(input_file)?;
+    let png = PngRef::try_from(&bytes[..])?;
+    let mut after_end = false;
+    let mut hits = Vec::new();
+    for (chunk, record) in png.chunks().iter().zip(chunk_records(&png)) {
+        let chunk_type = chunk.chunk_type();
+        if after_end || (!chunk_type.is_critical() && !chunk_type.is_public()) {
+            hits.push(ScanHit { chunk: record, payload: PayloadKind::of(chunk.data()), after_iend: after_end });
+        }
+        if chunk_type.to_string() == "IEND" {
+            after_end = true;
+        }
+    }
+    if args.format.is_json() {
+        return records::write_json(out, &ScanRecord { file: input_file, messages: hits });
+    }
+    for hit in hits.iter() {
+        let location = if hit.after_iend { ", after IEND" } else { "" };
+        writeln!(
+            out,
+            "{} at offset {}: {} bytes, {}{}",
+            hit.chunk.chunk_type,
+            hit.chunk.offset,
+            hit.chunk.length,
+            hit.payload,
+            location
+        )?;
+    }
+    writeln!(out, "{} possible messages", hits.len())?;
+    Ok(())
+}
+
+// the image in a file, or stdin for `-`, refusing a file with an unfinished append
+fn read_png(path: &Path) -> Result<Png, PngError> {
+    Png::try_from(stdio::read(path)?.as_slice())
+}
+
+// every chunk of the image as it appears in JSON output
+fn chunk_records(png: &PngRef) -> Vec<ChunkRecord> {
+    png.chunks().iter()
+        .zip(png.chunk_offsets())
+        .enumerate()
+        .map(|(index, (chunk, offset))| ChunkRecord::new(index, offset, chunk))
+        .collect()
+}
+
+// where a command writes its result for `input_file`: inside --out-dir when given,
+// otherwise `out_path`; None when neither applies
+fn output_path(
+    inputs: &Inputs,
+    input_file: &Path,
+    out_path: Option<&Path>,
+    output: &OutputArgs,
+) -> Result<Option<PathBuf>, PngError> {
+    if let Some(out_dir) = &output.out_dir {
+        let path = inputs.output_path(input_file, out_dir, &output.name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return Ok(Some(path));
+    }
+    Ok(out_path.map(Path::to_path_buf))
+}
+
+/// Lists the messages in a PNG file with their index among chunks of the same type, size and offset
+pub fn messages(args: &MessagesArgs) -> Result<(), PngError> {
+    let png = read_png(&args.file_path)?;
+    if let Some(chunk_type) = &args.chunk_type {
+        ChunkType::from_str(chunk_type)?;
+    }
+    let offsets = png.chunk_offsets();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    println!("{:<6}{:<6}{:>12}{:>12}", "index", "type", "size", "offset");
+    for (chunk, offset) in png.chunks().iter().zip(offsets) {
+        let chunk_type = chunk.chunk_type().to_string();
+        let index = seen.entry(chunk_type.clone()).or_insert(0);
+        let listed = match &args.chunk_type {
+            Some(wanted) => *wanted == chunk_type,
+            None => !chunk.chunk_type().is_public(),
+        };
+        if listed {
+            println!("{:<6}{:<6}{:>12}{:>12}", index, chunk_type, chunk.data().len(), offset);
+        }
+        *index += 1;
+    }
+    Ok(())
+}
+
+/// Generates a key pair, writing it to files or printing it when no path is given
+pub fn keygen(args: &KeygenArgs) -> Result<(), PngError> {
+    let (contents, public_key) = if args.signing {
+        let identity = SigningIdentity::generate();
+        (identity.to_file_contents(), identity.signer_key().to_string())
+    } else {
+        let identity = Identity::generate();
+        (identity.to_file_contents(), identity.public_key().to_string())
+    };
+    match args.out_path.as_deref().filter(|path| !stdio::is_stdio(path)) {
+        None => print!("{}", contents),
+        Some(out_path) => {
+            let mut pub_path = out_path.as_os_str().to_os_string();
+            pub_path.push(".pub");
+            // the identity is a secret, only its owner may read it
+            atomic::write_private(out_path, contents.as_bytes())?;
+            stdio::write(Path::new(&pub_path), format!("{}\n", public_key).as_bytes(), false)?;
+            println!("public key: {}", public_key);
+        }
+    }
+    Ok(())
+}
+
+/// Signs the critical chunks and selected messages of a PNG file and saves the result
+pub fn sign(args: &SignArgs) -> Result<(), PngError> {
+    let identity = SigningIdentity::from_file(&args.key_path)?;
+    let message_types = args.chunk_types.iter()
+        .map(|chunk_type| ChunkType::from_str(chunk_type))
+        .collect::<Result<Vec<ChunkType>, PngError>>()?;
+    let mut png = read_png(&args.file_path)?;
+    // drop any previous signature before signing again
+    png.remove_chunks_by_type(signature::SIGNATURE_CHUNK_TYPE).ok();
+    let signature_chunk = signature::sign(&png, &identity, &message_types)?;
+    // before IEND so the signed image stays valid
+    png.insert_before_end([signature_chunk])?;
+    let output_path = args.out_path.as_ref().unwrap_or(&args.file_path);
+    stdio::write(output_path, &png.as_bytes(), args.backup.keep)?;
+    // keep stdout for the image when it is written there
+    match stdio::is_stdio(output_path) {
+        true => eprintln!("signed by {}", identity.signer_key()),
+        false => println!("signed by {}", identity.signer_key()),
+    }
+    Ok(())
+}
+
+/// Verifies the signature of a PNG file and reports any signed chunks that changed
+pub fn verify(args: &VerifyArgs) -> Result<(), PngError> {
+    let trusted = SignerKey::from_arg(&args.public_key)?;
+    let png = read_png(&args.file_path)?;
+    let verification = signature::verify(&png, &trusted)?;
+    println!("signed by {}", verification.signer);
+    if !verification.trusted {
+        println!("signer does not match the expected public key");
+        return Err(PngError::VerificationFailed);
+    }
+    if !verification.signature_valid {
+        println!("signature does not match its manifest");
+    }
+    for id in verification.added.iter() {
+        println!("added: {}", id);
+    }
+    for id in verification.removed.iter() {
+        println!("removed: {}", id);
+    }
+    for id in verification.modified.iter() {
+        println!("modified: {}", id);
+    }
+    match verification.is_valid() {
+        true => {
+            println!("signature is valid");
+            Ok(())
+        }
+        false => Err(PngError::VerificationFailed),
+    }
+}
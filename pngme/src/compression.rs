@@ -0,0 +1,149 @@
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use crate::png_error::PngError;
+
+/**
+compressed payload layout:
+magic(3) | codec(1) | compressed data
+ */
+const MAGIC: &[u8; 3] = b"pmZ";
+const ZSTD_LEVEL: i32 = 19;
+
+/// Default limit on the size of a decompressed message
+pub const DEFAULT_MAX_OUTPUT: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Deflate,
+    Zstd,
+}
+
+impl Codec {
+    fn id(&self) -> u8 {
+        match self {
+            Codec::Deflate => 1,
+            Codec::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Codec> {
+        match id {
+            1 => Some(Codec::Deflate),
+            2 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if the data looks like the output of `compress`
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() > MAGIC.len() && data.starts_with(MAGIC) && Codec::from_id(data[MAGIC.len()]).is_some()
+}
+
+/// Compresses the data with the given codec and records the codec in front of it
+pub fn compress(data: &[u8], codec: Codec) -> Result<Vec<u8>, PngError> {
+    let mut res = MAGIC.to_vec();
+    res.push(codec.id());
+    match codec {
+        Codec::Deflate => {
+            let mut encoder = DeflateEncoder::new(res, flate2::Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Codec::Zstd => {
+            res.extend(zstd::encode_all(data, ZSTD_LEVEL)?);
+            Ok(res)
+        }
+    }
+}
+
+/// Tries every codec and returns the smallest result, or None if none of them shrinks the data
+pub fn compress_smallest(data: &[u8]) -> Result<Option<Vec<u8>>, PngError> {
+    let mut best: Option<Vec<u8>> = None;
+    for codec in [Codec::Deflate, Codec::Zstd] {
+        let compressed = compress(data, codec)?;
+        if compressed.len() < best.as_ref().map_or(data.len(), |b| b.len()) {
+            best = Some(compressed);
+        }
+    }
+    Ok(best)
+}
+
+/// Decompresses data produced by `compress`, refusing to produce more than `max_output` bytes
+pub fn decompress(data: &[u8], max_output: usize) -> Result<Vec<u8>, PngError> {
+    if !is_compressed(data) {
+        return Err(PngError::CompressionError);
+    }
+    let body = &data[MAGIC.len() + 1..];
+    let reader: Box<dyn Read> = match Codec::from_id(data[MAGIC.len()]) {
+        Some(Codec::Deflate) => Box::new(DeflateDecoder::new(body)),
+        Some(Codec::Zstd) => Box::new(zstd::Decoder::new(body)?),
+        None => return Err(PngError::CompressionError),
+    };
+    // read one byte past the limit so an oversized message can be told apart from one that fits
+    let mut res = Vec::new();
+    reader
+        .take(max_output as u64 + 1)
+        .read_to_end(&mut res)
+        .map_err(|_| PngError::CompressionError)?;
+    if res.len() > max_output {
+        return Err(PngError::DecompressionLimit(max_output));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_message() -> Vec<u8> {
+        "This is where your secret message will be! ".repeat(100).into_bytes()
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let message = testing_message();
+        let compressed = compress(&message, Codec::Deflate).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < message.len());
+        assert_eq!(decompress(&compressed, DEFAULT_MAX_OUTPUT).unwrap(), message);
+    }
+
+    #[test]
+    fn test_zstd_round_trip() {
+        let message = testing_message();
+        let compressed = compress(&message, Codec::Zstd).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < message.len());
+        assert_eq!(decompress(&compressed, DEFAULT_MAX_OUTPUT).unwrap(), message);
+    }
+
+    #[test]
+    fn test_compress_smallest() {
+        let message = testing_message();
+        let compressed = compress_smallest(&message).unwrap().unwrap();
+        assert_eq!(decompress(&compressed, DEFAULT_MAX_OUTPUT).unwrap(), message);
+
+        assert!(compress_smallest(b"hi").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let message = testing_message();
+        let compressed = compress(&message, Codec::Zstd).unwrap();
+        assert!(decompress(&compressed, message.len()).is_ok());
+        assert!(matches!(
+            decompress(&compressed, message.len() - 1),
+            Err(PngError::DecompressionLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_plain_data_is_not_compressed() {
+        assert!(!is_compressed(b"This is where your secret message will be!"));
+        assert!(decompress(b"pmZ\x07garbage", DEFAULT_MAX_OUTPUT).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{SharedSecret, StaticSecret};
+
+use crate::png_error::PngError;
+use crate::stdio;
+
+/**
+sealed payload layout:
+magic(4) | ephemeral public key(32) | recipient count(u16) | wrapped keys(48 each) | nonce(12) | ciphertext
+ */
+const MAGIC: &[u8; 4] = b"pmX1";
+const KEY_LEN: usize = 32;
+const WRAPPED_KEY_LEN: usize = KEY_LEN + 16;
+const NONCE_LEN: usize = 12;
+const WRAP_INFO: &[u8] = b"pngme x25519 key wrap";
+
+const PUBLIC_KEY_PREFIX: &str = "pngme-pk-";
+const SECRET_KEY_PREFIX: &str = "pngme-sk-";
+
+/// A recipient's X25519 public key, written as `pngme-pk-<hex>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PublicKey(x25519_dalek::PublicKey);
+
+/// An X25519 key pair used to open sealed messages, written as `pngme-sk-<hex>`
+pub struct Identity {
+    secret: StaticSecret,
+}
+
+impl PublicKey {
+    /// Parses a public key given either inline or as the path of a key file
+    pub fn from_arg(arg: &str) -> Result<PublicKey, PngError> {
+        if arg.starts_with(PUBLIC_KEY_PREFIX) {
+            return PublicKey::from_str(arg);
+        }
+        let text = stdio::read_to_string(Path::new(arg))?;
+        PublicKey::from_str(first_key_line(&text)?)
+    }
+}
+
+impl Identity {
+    pub fn generate() -> Identity {
+        Identity {
+            secret: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Identity, PngError> {
+        let text = stdio::read_to_string(path.as_ref())?;
+        Identity::from_str(first_key_line(&text)?)
+    }
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519_dalek::PublicKey::from(&self.secret))
+    }
+    /// Contents of an identity file, with the public key kept as a comment for reference
+    pub fn to_file_contents(&self) -> String {
+        format!("# created by pngme keygen\n# public key: {}\n{}\n", self.public_key(), self)
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, PngError> {
+        let bytes = parse_key(s, PUBLIC_KEY_PREFIX)?;
+        Ok(PublicKey(x25519_dalek::PublicKey::from(bytes)))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, PngError> {
+        let bytes = parse_key(s, SECRET_KEY_PREFIX)?;
+        Ok(Identity {
+            secret: StaticSecret::from(bytes),
+        })
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", PUBLIC_KEY_PREFIX, hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", SECRET_KEY_PREFIX, hex::encode(self.secret.as_bytes()))
+    }
+}
+
+/// Returns true if the data looks like the output of `seal`
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts the payload once under a random content key and wraps that key for every recipient
+pub fn seal(payload: &[u8], recipients: &[PublicKey]) -> Result<Vec<u8>, PngError> {
+    if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+        return Err(PngError::KeyError);
+    }
+    let content_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral);
+
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(ephemeral_public.as_bytes());
+    header.extend_from_slice(&(recipients.len() as u16).to_be_bytes());
+    for recipient in recipients {
+        let shared = ephemeral.diffie_hellman(&recipient.0);
+        let kek = wrap_key(shared, &ephemeral_public, &recipient.0)?;
+        let wrapped = ChaCha20Poly1305::new(&kek)
+            .encrypt(&Nonce::default(), content_key.as_slice())
+            .map_err(|_| PngError::KeyError)?;
+        header.extend_from_slice(&wrapped);
+    }
+
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(&content_key)
+        .encrypt(&nonce, Payload { msg: payload, aad: &header })
+        .map_err(|_| PngError::KeyError)?;
+    header.extend_from_slice(&nonce);
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Decrypts a payload produced by `seal` if the identity is one of its recipients
+pub fn open(sealed: &[u8], identity: &Identity) -> Result<Vec<u8>, PngError> {
+    if !is_sealed(sealed) || sealed.len() < MAGIC.len() + KEY_LEN + 2 {
+        return Err(PngError::SealedPayloadError);
+    }
+    let mut pos = MAGIC.len();
+    let ephemeral_bytes: [u8; KEY_LEN] = sealed[pos..pos + KEY_LEN].try_into().unwrap();
+    let ephemeral_public = x25519_dalek::PublicKey::from(ephemeral_bytes);
+    pos += KEY_LEN;
+    let count = u16::from_be_bytes([sealed[pos], sealed[pos + 1]]) as usize;
+    pos += 2;
+    let header_len = pos + count * WRAPPED_KEY_LEN;
+    if sealed.len() < header_len + NONCE_LEN {
+        return Err(PngError::SealedPayloadError);
+    }
+    let (header, body) = sealed.split_at(header_len);
+
+    let shared = identity.secret.diffie_hellman(&ephemeral_public);
+    let kek = wrap_key(shared, &ephemeral_public, &identity.public_key().0)
+        .map_err(|_| PngError::DecryptError)?;
+    let cipher = ChaCha20Poly1305::new(&kek);
+    let content_key = header[pos..]
+        .chunks(WRAPPED_KEY_LEN)
+        .find_map(|wrapped| cipher.decrypt(&Nonce::default(), wrapped).ok())
+        .ok_or(PngError::DecryptError)?;
+
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(Key::from_slice(&content_key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| PngError::DecryptError)
+}
+
+// derive the key-encryption key shared between the ephemeral key and one recipient
+fn wrap_key(
+    shared: SharedSecret,
+    ephemeral_public: &x25519_dalek::PublicKey,
+    recipient: &x25519_dalek::PublicKey,
+) -> Result<Key, PngError> {
+    if !shared.was_contributory() {
+        return Err(PngError::KeyError);
+    }
+    let salt = [ephemeral_public.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut kek = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(WRAP_INFO, &mut kek)
+        .map_err(|_| PngError::KeyError)?;
+    Ok(kek)
+}
+
+pub(crate) fn parse_key(s: &str, prefix: &str) -> Result<[u8; KEY_LEN], PngError> {
+    let encoded = s.trim().strip_prefix(prefix).ok_or(PngError::KeyError)?;
+    let bytes = hex::decode(encoded).map_err(|_| PngError::KeyError)?;
+    <[u8; KEY_LEN]>::try_from(bytes).map_err(|_| PngError::KeyError)
+}
+
+pub(crate) fn first_key_line(text: &str) -> Result<&str, PngError> {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or(PngError::KeyError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_multiple_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let recipients = [alice.public_key(), bob.public_key()];
+        let sealed = seal(b"meet at noon", &recipients).unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert_eq!(open(&sealed, &alice).unwrap(), b"meet at noon");
+        assert_eq!(open(&sealed, &bob).unwrap(), b"meet at noon");
+    }
+
+    #[test]
+    fn test_open_with_wrong_identity() {
+        let alice = Identity::generate();
+        let eve = Identity::generate();
+        let sealed = seal(b"meet at noon", &[alice.public_key()]).unwrap();
+
+        assert!(matches!(open(&sealed, &eve), Err(PngError::DecryptError)));
+    }
+
+    #[test]
+    fn test_open_tampered_payload() {
+        let alice = Identity::generate();
+        let mut sealed = seal(b"meet at noon", &[alice.public_key()]).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+
+        assert!(open(&sealed, &alice).is_err());
+    }
+
+    #[test]
+    fn test_seal_without_recipients() {
+        assert!(seal(b"meet at noon", &[]).is_err());
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let identity = Identity::generate();
+        let parsed = Identity::from_str(&identity.to_string()).unwrap();
+        assert_eq!(parsed.public_key(), identity.public_key());
+
+        let public = PublicKey::from_arg(&identity.public_key().to_string()).unwrap();
+        assert_eq!(public, identity.public_key());
+
+        let file_contents = identity.to_file_contents();
+        let parsed = Identity::from_str(first_key_line(&file_contents).unwrap()).unwrap();
+        assert_eq!(parsed.public_key(), identity.public_key());
+    }
+
+    #[test]
+    fn test_invalid_key_text() {
+        assert!(PublicKey::from_str("pngme-pk-1234").is_err());
+        assert!(Identity::from_str(&Identity::generate().public_key().to_string()).is_err());
+    }
+}
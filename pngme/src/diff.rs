@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::signature::ChunkId;
+use crate::summary;
+
+/// Differences between the chunk lists of two images. Chunks are matched by type and by
+/// their position among chunks of the same type, the same way signatures identify them.
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// chunks only in the new image, with their index there
+    pub added: Vec<(ChunkId, usize)>,
+    /// chunks only in the old image, with their index there
+    pub removed: Vec<(ChunkId, usize)>,
+    /// chunks in both images that changed place relative to the others, old and new index
+    pub moved: Vec<(ChunkId, usize, usize)>,
+    pub modified: Vec<Modified>,
+}
+
+/// A chunk in both images whose data differs
+#[derive(Debug)]
+pub struct Modified {
+    pub id: ChunkId,
+    pub old_length: usize,
+    pub new_length: usize,
+    /// offset into the data of the first byte that differs
+    pub first_difference: usize,
+    /// decoded fields that changed, empty for chunk types without known fields
+    pub fields: Vec<FieldChange>,
+}
+
+/// A decoded field of a known chunk type, None where the field is missing on one side
+#[derive(Debug, PartialEq)]
+pub struct FieldChange {
+    pub name: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Aligns the chunks of two images and reports what was added, removed, moved and modified
+pub fn diff(old: &Png, new: &Png) -> Diff {
+    let old_ids = chunk_ids(old);
+    let new_ids = chunk_ids(new);
+    let new_index: HashMap<ChunkId, usize> = new_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let old_index: HashMap<ChunkId, usize> = old_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    let mut res = Diff::default();
+    // chunks in both images, in old order, with their new index
+    let mut common = Vec::new();
+    for (i, id) in old_ids.iter().enumerate() {
+        match new_index.get(id) {
+            Some(j) => common.push((*id, i, *j)),
+            None => res.removed.push((*id, i)),
+        }
+    }
+    for (j, id) in new_ids.iter().enumerate() {
+        if !old_index.contains_key(id) {
+            res.added.push((*id, j));
+        }
+    }
+    // the longest run of chunks keeping their relative order stays put, the rest moved
+    let in_order = longest_increasing(&common.iter().map(|(_, _, j)| *j).collect::<Vec<usize>>());
+    for (k, (id, i, j)) in common.iter().enumerate() {
+        if !in_order.contains(&k) {
+            res.moved.push((*id, *i, *j));
+        }
+        let (old_chunk, new_chunk) = (&old.chunks()[*i], &new.chunks()[*j]);
+        if old_chunk.data() != new_chunk.data() {
+            res.modified.push(modified(*id, old_chunk, new_chunk));
+        }
+    }
+    res
+}
+
+fn chunk_ids(png: &Png) -> Vec<ChunkId> {
+    let mut seen: HashMap<[u8; 4], u32> = HashMap::new();
+    png.chunks().iter()
+        .map(|chunk| {
+            let occurrence = seen.entry(chunk.chunk_type().bytes()).or_insert(0);
+            *occurrence += 1;
+            ChunkId::new(chunk.chunk_type(), *occurrence - 1)
+        })
+        .collect()
+}
+
+fn modified(id: ChunkId, old: &Chunk, new: &Chunk) -> Modified {
+    let first_difference = old.data().iter()
+        .zip(new.data())
+        .position(|(a, b)| a != b)
+        .unwrap_or(old.data().len().min(new.data().len()));
+    let fields = match (summary::fields(&old.as_chunk_ref()), summary::fields(&new.as_chunk_ref())) {
+        (Some(old_fields), Some(new_fields)) => field_changes(old_fields, new_fields),
+        _ => Vec::new(),
+    };
+    Modified { id, old_length: old.data().len(), new_length: new.data().len(), first_difference, fields }
+}
+
+fn field_changes(old: Vec<(&'static str, String)>, new: Vec<(&'static str, String)>) -> Vec<FieldChange> {
+    let mut res = Vec::new();
+    for (name, value) in old.iter() {
+        let new_value = new.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+        if new_value != Some(value) {
+            res.push(FieldChange { name, old: Some(value.clone()), new: new_value.cloned() });
+        }
+    }
+    for (name, value) in new.iter().filter(|(name, _)| !old.iter().any(|(n, _)| n == name)) {
+        res.push(FieldChange { name, old: None, new: Some(value.clone()) });
+    }
+    res
+}
+
+// positions of one longest strictly increasing subsequence
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // best[i] is the length of the longest run ending at i, previous[i] the position before it
+    let mut best = vec![1; values.len()];
+    let mut previous = vec![None; values.len()];
+    for i in 0..values.len() {
+        for j in 0..i {
+            if values[j] < values[i] && best[j] + 1 > best[i] {
+                best[i] = best[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+    let mut res = Vec::new();
+    let mut at = (0..values.len()).max_by_key(|i| (best[*i], std::cmp::Reverse(*i)));
+    while let Some(i) = at {
+        res.push(i);
+        at = previous[i];
+    }
+    res.reverse();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(
+            chunks.iter()
+                .map(|(chunk_type, data)| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()))
+                .collect(),
+        )
+    }
+
+    fn names(ids: Vec<ChunkId>) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let png = testing_png(&[("IHDR", b"h"), ("IDAT", b"x"), ("IEND", b"")]);
+        assert!(diff(&png, &png).is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_moved() {
+        let old = testing_png(&[("IHDR", b"h"), ("gAMA", b"g"), ("tEXt", b"t"), ("IDAT", b"x"), ("IEND", b"")]);
+        let new = testing_png(&[("IHDR", b"h"), ("tEXt", b"t"), ("IDAT", b"x"), ("gAMA", b"g"), ("IEND", b""), ("ruSt", b"r")]);
+        let diff = diff(&old, &new);
+        assert_eq!(names(diff.added.iter().map(|(id, _)| *id).collect()), vec!["ruSt#0"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].0.to_string(), "gAMA#0");
+        assert_eq!((diff.moved[0].1, diff.moved[0].2), (1, 3));
+        assert!(diff.modified.is_empty());
+    }
+
+    #[test]
+    fn test_modified_fields() {
+        let old = testing_png(&[("tEXt", b"Title\0old"), ("IDAT", b"abc"), ("IDAT", b"x")]);
+        let new = testing_png(&[("tEXt", b"Title\0new"), ("IDAT", b"abd")]);
+        let diff = diff(&old, &new);
+        assert_eq!(names(diff.removed.iter().map(|(id, _)| *id).collect()), vec!["IDAT#1"]);
+        assert_eq!(diff.modified.len(), 2);
+        assert_eq!(diff.modified[0].fields, vec![FieldChange {
+            name: "text",
+            old: Some("old".to_string()),
+            new: Some("new".to_string()),
+        }]);
+        assert!(diff.modified[1].fields.is_empty());
+        assert_eq!(diff.modified[1].first_difference, 2);
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        assert_eq!(longest_increasing(&[0, 3, 1, 2, 4]), vec![0, 2, 3, 4]);
+        assert!(longest_increasing(&[]).is_empty());
+    }
+}
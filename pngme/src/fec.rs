@@ -0,0 +1,184 @@
+use reed_solomon::{Decoder, Encoder};
+
+use crate::png_error::PngError;
+
+/**
+error corrected payload layout:
+header block(17) | data blocks
+the header block is magic(4) | parity bytes per block(u8) | payload length(u32), protected by its own 8 parity bytes
+each data block holds up to 255 - parity bytes of payload followed by its parity bytes
+ */
+const MAGIC: &[u8; 4] = b"pmR1";
+const HEADER_DATA_LEN: usize = MAGIC.len() + 1 + 4;
+const HEADER_PARITY: usize = 8;
+const HEADER_LEN: usize = HEADER_DATA_LEN + HEADER_PARITY;
+const BLOCK_LEN: usize = 255;
+
+/// Payload recovered by `decode` along with how many bytes had to be corrected
+#[derive(Debug)]
+pub struct Recovered {
+    pub data: Vec<u8>,
+    pub corrected: usize,
+}
+
+/// Adds `parity` Reed–Solomon parity bytes to every block of the payload
+pub fn encode(payload: &[u8], parity: u8) -> Result<Vec<u8>, PngError> {
+    let parity_len = parity as usize;
+    if !is_valid_parity(parity) {
+        return Err(PngError::ParityError(parity));
+    }
+    let payload_len = u32::try_from(payload.len()).map_err(|_| PngError::ParityError(parity))?;
+
+    let mut header = MAGIC.to_vec();
+    header.push(parity);
+    header.extend_from_slice(&payload_len.to_be_bytes());
+    let mut res = Encoder::new(HEADER_PARITY).encode(&header).to_vec();
+
+    let encoder = Encoder::new(parity_len);
+    for block in payload.chunks(BLOCK_LEN - parity_len) {
+        res.extend_from_slice(&encoder.encode(block));
+    }
+    Ok(res)
+}
+
+/// Corrects damaged bytes in data produced by `encode` and strips the parity bytes
+pub fn decode(data: &[u8]) -> Result<Recovered, PngError> {
+    let (parity, payload_len, mut corrected) = read_header(data).ok_or(PngError::UnrecoverableError)?;
+    if !is_valid_parity(parity) {
+        return Err(PngError::ParityError(parity));
+    }
+    let parity_len = parity as usize;
+    // the header comes from the file, check it against the blocks before allocating for them
+    let blocks = &data[HEADER_LEN..];
+    if payload_len > blocks.len() || blocks_len(payload_len, parity_len) != blocks.len() {
+        return Err(PngError::UnrecoverableError);
+    }
+    let decoder = Decoder::new(parity_len);
+
+    let mut res = Vec::with_capacity(payload_len);
+    for block in blocks.chunks(BLOCK_LEN) {
+        if block.len() <= parity_len {
+            return Err(PngError::UnrecoverableError);
+        }
+        let (buffer, fixed) = decoder
+            .correct_err_count(block, None)
+            .map_err(|_| PngError::UnrecoverableError)?;
+        res.extend_from_slice(buffer.data());
+        corrected += fixed;
+    }
+    if res.len() != payload_len {
+        return Err(PngError::UnrecoverableError);
+    }
+    Ok(Recovered { data: res, corrected })
+}
+
+// at least one parity byte, and room for at least one payload byte in every block
+fn is_valid_parity(parity: u8) -> bool {
+    parity != 0 && (parity as usize) < BLOCK_LEN - 1
+}
+
+// length of the data blocks `encode` writes for a payload
+fn blocks_len(payload_len: usize, parity_len: usize) -> usize {
+    let block_data = BLOCK_LEN - parity_len;
+    let last = match payload_len % block_data {
+        0 => 0,
+        rest => rest + parity_len,
+    };
+    payload_len / block_data * BLOCK_LEN + last
+}
+
+// returns the parity bytes per block, the payload length and the number of corrected header bytes
+fn read_header(data: &[u8]) -> Option<(u8, usize, usize)> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let (buffer, corrected) = Decoder::new(HEADER_PARITY)
+        .correct_err_count(&data[..HEADER_LEN], None)
+        .ok()?;
+    let header = buffer.data();
+    if !header.starts_with(MAGIC) {
+        return None;
+    }
+    let parity = header[MAGIC.len()];
+    let payload_len = u32::from_be_bytes(header[MAGIC.len() + 1..].try_into().unwrap());
+    Some((parity, payload_len as usize, corrected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        "This is where your secret message will be! ".repeat(20).into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = testing_payload();
+        let encoded = encode(&payload, 16).unwrap();
+        assert!(read_header(&encoded).is_some());
+
+        let recovered = decode(&encoded).unwrap();
+        assert_eq!(recovered.data, payload);
+        assert_eq!(recovered.corrected, 0);
+    }
+
+    #[test]
+    fn test_corrects_damaged_bytes() {
+        let payload = testing_payload();
+        let mut encoded = encode(&payload, 16).unwrap();
+        // a couple of bad bytes in the header and in every data block
+        for pos in [1, 6, 20, 100, 300, 400, 700, 800] {
+            encoded[pos] ^= 0x5a;
+        }
+
+        let recovered = decode(&encoded).unwrap();
+        assert_eq!(recovered.data, payload);
+        assert_eq!(recovered.corrected, 8);
+    }
+
+    #[test]
+    fn test_too_much_damage() {
+        let payload = testing_payload();
+        let mut encoded = encode(&payload, 4).unwrap();
+        for byte in encoded[HEADER_LEN..HEADER_LEN + 10].iter_mut() {
+            *byte ^= 0xff;
+        }
+
+        assert!(decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_invalid_parity() {
+        assert!(encode(b"message", 0).is_err());
+        assert!(encode(b"message", 254).is_err());
+    }
+
+    // a header as `encode` writes it, with any parity and payload length
+    fn crafted_header(parity: u8, payload_len: u32) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(parity);
+        header.extend_from_slice(&payload_len.to_be_bytes());
+        Encoder::new(HEADER_PARITY).encode(&header).to_vec()
+    }
+
+    #[test]
+    fn test_crafted_header() {
+        let encoded = encode(&testing_payload(), 16).unwrap();
+        let blocks = &encoded[HEADER_LEN..];
+
+        let zero_parity = [crafted_header(0, 10), blocks.to_vec()].concat();
+        assert!(matches!(decode(&zero_parity), Err(PngError::ParityError(0))));
+        let full_parity = [crafted_header(255, 10), blocks.to_vec()].concat();
+        assert!(matches!(decode(&full_parity), Err(PngError::ParityError(255))));
+        let huge_payload = [crafted_header(16, u32::MAX), blocks.to_vec()].concat();
+        assert!(matches!(decode(&huge_payload), Err(PngError::UnrecoverableError)));
+        let longer_payload = [crafted_header(16, testing_payload().len() as u32 + 1), blocks.to_vec()].concat();
+        assert!(matches!(decode(&longer_payload), Err(PngError::UnrecoverableError)));
+    }
+
+    #[test]
+    fn test_plain_data_is_not_encoded() {
+        assert!(read_header(b"This is where your secret message will be!").is_none());
+    }
+}
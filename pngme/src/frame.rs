@@ -0,0 +1,111 @@
+use crate::split;
+
+/**
+framed payload layout:
+frame(1) | body
+the frame byte is 1000 followed by one bit per encoding applied to the body:
+split(8) | error corrected(4) | sealed(2) | compressed(1)
+0x80..=0x8f are UTF-8 continuation bytes, so no text message starts with a frame byte
+a message no encoding was applied to is stored as it is, only binary data that starts with a
+frame byte gets the empty frame 0x80 so it is not mistaken for an encoded message
+every piece of a split message is framed, its body is then the piece with its header
+ */
+const FRAME_MARK: u8 = 0x80;
+const MARK_MASK: u8 = 0xf0;
+pub const FRAME_LEN: usize = 1;
+
+/// The encodings `encode` applied to a message, recorded in front of it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Frame {
+    pub compressed: bool,
+    pub sealed: bool,
+    pub error_corrected: bool,
+    pub split: bool,
+}
+
+impl Frame {
+    /// Puts the frame byte in front of the body, a body without encodings is left as it is when
+    /// it cannot be read as framed data
+    pub fn wrap(&self, body: &[u8]) -> Vec<u8> {
+        if *self == Frame::default() && read(body).is_none() {
+            return body.to_vec();
+        }
+        let mut res = Vec::with_capacity(FRAME_LEN + body.len());
+        res.push(self.to_byte());
+        res.extend_from_slice(body);
+        res
+    }
+
+    fn to_byte(self) -> u8 {
+        FRAME_MARK
+            | self.compressed as u8
+            | (self.sealed as u8) << 1
+            | (self.error_corrected as u8) << 2
+            | (self.split as u8) << 3
+    }
+
+    fn from_byte(byte: u8) -> Option<Frame> {
+        if byte & MARK_MASK != FRAME_MARK {
+            return None;
+        }
+        Some(Frame {
+            compressed: byte & 1 != 0,
+            sealed: byte & 2 != 0,
+            error_corrected: byte & 4 != 0,
+            split: byte & 8 != 0,
+        })
+    }
+}
+
+/// Splits chunk data into its frame and body, None when the data was not written by pngme
+pub fn read(data: &[u8]) -> Option<(Frame, &[u8])> {
+    let frame = Frame::from_byte(*data.first()?)?;
+    Some((frame, &data[FRAME_LEN..]))
+}
+
+/// The piece inside chunk data holding one piece of a split message
+pub fn piece(data: &[u8]) -> Option<&[u8]> {
+    read(data)
+        .filter(|(frame, body)| frame.split && split::is_piece(body))
+        .map(|(_, body)| body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frame = Frame { compressed: true, sealed: false, error_corrected: true, split: false };
+        let data = frame.wrap(b"body");
+        assert_eq!(data[0], 0x85);
+        assert_eq!(read(&data), Some((frame, &b"body"[..])));
+    }
+
+    #[test]
+    fn test_plain_data_is_not_framed() {
+        assert_eq!(Frame::default().wrap(b"hello"), b"hello");
+        assert_eq!(Frame::default().wrap(b""), b"");
+        // binary data that would pass for a frame keeps an empty one
+        let framed = Frame::default().wrap(&[0x85, 1]);
+        assert_eq!(framed, [0x80, 0x85, 1]);
+        assert_eq!(read(&framed), Some((Frame::default(), &[0x85, 1][..])));
+    }
+
+    #[test]
+    fn test_unframed_data() {
+        // plain text that happens to look like an encoded payload is not mistaken for one
+        assert_eq!(read(b"pmZ\x01 is where your secret message will be!"), None);
+        assert_eq!(read("é".as_bytes()), None);
+        assert_eq!(read(b""), None);
+        assert_eq!(piece(&Frame::default().wrap(b"pmP1 but not a piece")), None);
+    }
+
+    #[test]
+    fn test_piece() {
+        let pieces = split::split(b"message", 30).unwrap();
+        let frame = Frame { split: true, ..Frame::default() };
+        assert_eq!(piece(&frame.wrap(&pieces[0])), Some(pieces[0].as_slice()));
+        assert_eq!(piece(&Frame::default().wrap(&pieces[0])), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+use std::ops::Range;
+
+use crate::png_error::PngError;
+use crate::style::paint;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Writes a hex and ASCII dump of `bytes`, 16 per line, with addresses counted from `start`.
+/// `style_of` gives the colour of the byte at each address.
+pub fn write_dump<S>(
+    out: &mut dyn Write,
+    bytes: &[u8],
+    start: usize,
+    style_of: S,
+    color: bool,
+) -> Result<(), PngError>
+where
+    S: Fn(usize) -> &'static str,
+{
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let address = start + line * BYTES_PER_LINE;
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for i in 0..BYTES_PER_LINE {
+            if i == BYTES_PER_LINE / 2 {
+                hex.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => {
+                    let style = style_of(address + i);
+                    hex.push_str(&paint(&format!("{:02x}", byte), style, color));
+                    hex.push(' ');
+                    let c = if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' };
+                    ascii.push_str(&paint(&c.to_string(), style, color));
+                }
+                None => hex.push_str("   "),
+            }
+        }
+        writeln!(out, "{:08x}  {} |{}|", address, hex, ascii)?;
+    }
+    Ok(())
+}
+
+/// Parses a range of the form `start..end`, `start..` or `..end` and checks it fits in `len` bytes
+pub fn parse_range(text: &str, len: usize) -> Result<Range<usize>, PngError> {
+    let error = || PngError::RangeError(text.to_string());
+    let (start, end) = text.split_once("..").ok_or_else(error)?;
+    let start = match start {
+        "" => 0,
+        start => start.parse().map_err(|_| error())?,
+    };
+    let end = match end {
+        "" => len,
+        end => end.parse().map_err(|_| error())?,
+    };
+    match start <= end && end <= len {
+        true => Ok(start..end),
+        false => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_lines() {
+        let bytes: Vec<u8> = (0x41..0x41 + 20).collect();
+        let mut out = Vec::new();
+        write_dump(&mut out, &bytes, 0x10, |_| "", false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000010  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|"
+        );
+        assert_eq!(
+            lines[1],
+            "00000020  51 52 53 54                                       |QRST|"
+        );
+    }
+
+    #[test]
+    fn test_dump_color() {
+        let mut out = Vec::new();
+        write_dump(&mut out, &[0, 1], 0, |address| if address == 0 { "31" } else { "" }, true).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("00000000  \x1b[31m00\x1b[0m 01 "));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("2..5", 10).unwrap(), 2..5);
+        assert_eq!(parse_range("2..", 10).unwrap(), 2..10);
+        assert_eq!(parse_range("..4", 10).unwrap(), 0..4);
+        assert_eq!(parse_range("..", 10).unwrap(), 0..10);
+        assert!(parse_range("5..2", 10).is_err());
+        assert!(parse_range("0..11", 10).is_err());
+        assert!(parse_range("3", 10).is_err());
+        assert!(parse_range("a..b", 10).is_err());
+    }
+}
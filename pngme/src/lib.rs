@@ -0,0 +1,63 @@
+//! Reading, editing and writing PNG chunks, and the commands of the pngme CLI built on them.
+//! Enable the `serde` feature to serialize `Png`, `Chunk` and `ChunkType`. The default `cli`
+//! feature builds the pngme binary, turn it off to depend on the chunk API alone.
+
+pub mod chunk;
+pub mod chunk_type;
+pub mod png;
+pub mod png_error;
+
+mod utils;
+
+// used by the pngme binary, not part of the library API
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod args;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod commands;
+
+#[cfg(feature = "cli")]
+mod append;
+#[cfg(feature = "cli")]
+mod atomic;
+#[cfg(feature = "cli")]
+mod batch;
+#[cfg(feature = "cli")]
+mod compression;
+#[cfg(feature = "cli")]
+mod crypto;
+#[cfg(feature = "cli")]
+mod diff;
+#[cfg(feature = "cli")]
+mod fec;
+#[cfg(feature = "cli")]
+mod frame;
+#[cfg(feature = "cli")]
+mod hexdump;
+#[cfg(feature = "cli")]
+mod manifest;
+#[cfg(feature = "cli")]
+mod pixels;
+#[cfg(feature = "cli")]
+mod policy;
+#[cfg(feature = "cli")]
+mod reader;
+#[cfg(feature = "cli")]
+mod records;
+#[cfg(feature = "cli")]
+mod repair;
+#[cfg(feature = "cli")]
+mod signature;
+#[cfg(feature = "cli")]
+mod split;
+#[cfg(feature = "cli")]
+mod stdio;
+#[cfg(feature = "cli")]
+mod strip;
+#[cfg(feature = "cli")]
+mod style;
+#[cfg(feature = "cli")]
+mod summary;
+#[cfg(feature = "cli")]
+mod validate;
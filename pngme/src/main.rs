@@ -1,22 +1,37 @@
-mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
-mod png_error;
-mod utils;
+use std::process;
 
-use crate::png_error::PngError;
 use clap::Parser;
 
-fn main() -> anyhow::Result<(), PngError> {
+use pngme::{args, commands};
+
+fn main() {
     let cli = args::Cli::parse();
 
-    return match &cli.command {
+    let result = match &cli.command {
         args::Command::Encode(cmd) => commands::encode(cmd),
         args::Command::Decode(cmd) => commands::decode(cmd),
+        args::Command::Update(cmd) => commands::update(cmd),
         args::Command::Remove(cmd) => commands::remove(cmd),
-        args::Command::Print(cmd) => commands::print_chunks(cmd)
+        args::Command::Strip(cmd) => commands::strip(cmd),
+        args::Command::Print(cmd) => commands::print_chunks(cmd),
+        args::Command::Inspect(cmd) => commands::inspect(cmd),
+        args::Command::Dump(cmd) => commands::dump(cmd),
+        args::Command::Transplant(cmd) => commands::transplant(cmd),
+        args::Command::Diff(cmd) => commands::diff(cmd),
+        args::Command::Unpack(cmd) => commands::unpack(cmd),
+        args::Command::Pack(cmd) => commands::pack(cmd),
+        args::Command::Validate(cmd) => commands::validate(cmd),
+        args::Command::Repair(cmd) => commands::repair(cmd),
+        args::Command::Scan(cmd) => commands::scan(cmd),
+        args::Command::Messages(cmd) => commands::messages(cmd),
+        args::Command::Keygen(cmd) => commands::keygen(cmd),
+        args::Command::Sign(cmd) => commands::sign(cmd),
+        args::Command::Verify(cmd) => commands::verify(cmd),
+    };
+    // print the error message rather than its Debug form so hints reach the user
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(1);
     }
 }
 
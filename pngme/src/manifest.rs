@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::png_error::PngError;
+
+pub const JSON_MANIFEST: &str = "manifest.json";
+pub const TOML_MANIFEST: &str = "manifest.toml";
+const TRAILING_FILE: &str = "trailing.bin";
+
+/// Order and types of the chunks written by `unpack`, which `pack` rebuilds the image from
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub chunks: Vec<ManifestEntry>,
+    /// file holding whatever followed IEND in the original image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing: Option<String>,
+}
+
+/// One chunk, its data is in `file` relative to the manifest. Length and CRC are only there
+/// for reference, `pack` recomputes them.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crc: Option<u32>,
+}
+
+/// Manifest file formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManifestFormat {
+    Json,
+    Toml,
+}
+
+impl ManifestFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ManifestFormat::Json => JSON_MANIFEST,
+            ManifestFormat::Toml => TOML_MANIFEST,
+        }
+    }
+}
+
+/// Writes the data of every chunk into its own file in `dir` plus a manifest, returning it
+pub fn unpack(png: &Png, trailing: &[u8], dir: &Path, format: ManifestFormat) -> Result<Manifest, PngError> {
+    fs::create_dir_all(dir)?;
+    let mut chunks = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let file = format!("{:03}-{}.bin", index, chunk.chunk_type());
+        fs::write(dir.join(&file), chunk.data())?;
+        chunks.push(ManifestEntry {
+            chunk_type: chunk.chunk_type().to_string(),
+            file,
+            length: Some(chunk.data().len()),
+            crc: Some(chunk.crc()),
+        });
+    }
+    let trailing = match trailing.is_empty() {
+        true => None,
+        false => {
+            fs::write(dir.join(TRAILING_FILE), trailing)?;
+            Some(TRAILING_FILE.to_string())
+        }
+    };
+    let manifest = Manifest { chunks, trailing };
+    let text = match format {
+        ManifestFormat::Json => serde_json::to_string_pretty(&manifest)
+            .map_err(|err| PngError::ManifestError(err.to_string()))?,
+        ManifestFormat::Toml => toml::to_string(&manifest).map_err(|err| PngError::ManifestError(err.to_string()))?,
+    };
+    fs::write(dir.join(format.file_name()), text)?;
+    Ok(manifest)
+}
+
+/// The files an earlier `unpack` wrote into `dir`: manifests, chunk files and trailing bytes
+pub fn unpacked_files(dir: &Path) -> Result<Vec<PathBuf>, PngError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut res = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_unpacked = name.to_str().is_some_and(|name| {
+            [JSON_MANIFEST, TOML_MANIFEST, TRAILING_FILE].contains(&name) || is_chunk_file(name)
+        });
+        if is_unpacked && entry.file_type()?.is_file() {
+            res.push(entry.path());
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+// the `{:03}-{type}.bin` names given by `unpack`
+fn is_chunk_file(name: &str) -> bool {
+    let Some((index, rest)) = name.split_once('-') else {
+        return false;
+    };
+    let Some(chunk_type) = rest.strip_suffix(".bin") else {
+        return false;
+    };
+    index.len() >= 3
+        && index.bytes().all(|b| b.is_ascii_digit())
+        && chunk_type.len() == 4
+        && chunk_type.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// Reads the manifest in `dir` and rebuilds the image, recomputing every length and CRC
+pub fn pack(dir: &Path) -> Result<Vec<u8>, PngError> {
+    let manifest = read_manifest(dir)?;
+    let mut chunks = Vec::new();
+    for entry in manifest.chunks.iter() {
+        let chunk_type = ChunkType::from_str(&entry.chunk_type)?;
+        chunks.push(Chunk::new(chunk_type, fs::read(data_path(dir, &entry.file)?)?));
+    }
+    let mut res = Png::from_chunks(chunks).as_bytes();
+    if let Some(trailing) = &manifest.trailing {
+        res.extend(fs::read(data_path(dir, trailing)?)?);
+    }
+    Ok(res)
+}
+
+fn read_manifest(dir: &Path) -> Result<Manifest, PngError> {
+    let manifest_error = |err: String| PngError::ManifestError(err);
+    let json_path = dir.join(JSON_MANIFEST);
+    if json_path.exists() {
+        return serde_json::from_slice(&fs::read(json_path)?).map_err(|err| manifest_error(err.to_string()));
+    }
+    let toml_path = dir.join(TOML_MANIFEST);
+    if toml_path.exists() {
+        return toml::from_str(&fs::read_to_string(toml_path)?).map_err(|err| manifest_error(err.to_string()));
+    }
+    Err(manifest_error(format!("no {} or {} in {}", JSON_MANIFEST, TOML_MANIFEST, dir.display())))
+}
+
+// chunk files must stay inside the unpacked directory
+fn data_path(dir: &Path, file: &str) -> Result<PathBuf, PngError> {
+    let path = Path::new(file);
+    match path.components().all(|component| matches!(component, Component::Normal(_))) {
+        true => Ok(dir.join(path)),
+        false => Err(PngError::ManifestError(format!("{} is not inside the manifest directory", file))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunks = [("IHDR", "header"), ("tEXt", "Title\0pngme"), ("IDAT", "pixels"), ("IEND", "")]
+            .iter()
+            .map(|(chunk_type, data)| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec()))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ManifestFormat::Json, ManifestFormat::Toml] {
+            let dir = tempfile::tempdir().unwrap();
+            let png = testing_png();
+            let manifest = unpack(&png, b"after", dir.path(), format).unwrap();
+            assert_eq!(manifest.chunks[1].file, "001-tEXt.bin");
+            assert!(dir.path().join(format.file_name()).exists());
+
+            let mut expected = png.as_bytes();
+            expected.extend_from_slice(b"after");
+            assert_eq!(pack(dir.path()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_pack_recomputes_crc() {
+        let dir = tempfile::tempdir().unwrap();
+        unpack(&testing_png(), &[], dir.path(), ManifestFormat::Json).unwrap();
+        fs::write(dir.path().join("001-tEXt.bin"), b"Title\0edited by hand").unwrap();
+
+        let packed = pack(dir.path()).unwrap();
+        let png = Png::try_from(packed.as_slice()).unwrap();
+        assert_eq!(png.chunks()[1].data(), b"Title\0edited by hand");
+    }
+
+    #[test]
+    fn test_unpacked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(unpacked_files(&dir.path().join("missing")).unwrap().is_empty());
+        unpack(&testing_png(), b"after", dir.path(), ManifestFormat::Toml).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"mine").unwrap();
+        fs::write(dir.path().join("01-tEXt.bin"), b"mine").unwrap();
+
+        let names: Vec<_> = unpacked_files(dir.path()).unwrap()
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["000-IHDR.bin", "001-tEXt.bin", "002-IDAT.bin", "003-IEND.bin", TOML_MANIFEST, TRAILING_FILE]);
+    }
+
+    #[test]
+    fn test_pack_hand_written_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("text.bin"), b"hi").unwrap();
+        fs::write(dir.path().join(TOML_MANIFEST), "[[chunks]]\ntype = \"tEXt\"\nfile = \"text.bin\"\n").unwrap();
+        let packed = pack(dir.path()).unwrap();
+        assert_eq!(Png::try_from(packed.as_slice()).unwrap().chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_pack_rejects_outside_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = r#"{"chunks": [{"type": "tEXt", "file": "../secret"}]}"#;
+        fs::write(dir.path().join(JSON_MANIFEST), manifest).unwrap();
+        assert!(matches!(pack(dir.path()), Err(PngError::ManifestError(_))));
+        assert!(matches!(pack(Path::new("/nonexistent")), Err(PngError::ManifestError(_))));
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Cursor;
+
+use png_codec::{BitDepth, ColorType, Decoder, Transformations};
+
+use crate::png::Png;
+use crate::png_error::PngError;
+
+/// Decoded image with every pixel widened to 16-bit RGBA, so images stored with different
+/// colour types or bit depths can be compared
+#[derive(Debug)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<[u16; 4]>,
+}
+
+/// How the pixels of two images compare
+#[derive(Debug, PartialEq)]
+pub enum PixelComparison {
+    Identical,
+    DifferentSize { old: (u32, u32), new: (u32, u32) },
+    Different { differing: usize, total: usize },
+}
+
+/// Decodes the image data of a PNG
+pub fn decode(png: &Png) -> Result<Pixels, PngError> {
+    let pixel_error = |err: png_codec::DecodingError| PngError::PixelError(err.to_string());
+    let mut decoder = Decoder::new(Cursor::new(png.as_bytes()));
+    // palettes and tRNS become plain channels, bit depths below 8 are widened to 8
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(pixel_error)?;
+    let size = reader.output_buffer_size().ok_or(PngError::PixelError("image too large".to_string()))?;
+    let mut buffer = vec![0; size];
+    let info = reader.next_frame(&mut buffer).map_err(pixel_error)?;
+
+    let channels = info.color_type.samples();
+    let sample_size = match info.bit_depth {
+        BitDepth::Sixteen => 2,
+        _ => 1,
+    };
+    let mut rgba = Vec::with_capacity(info.width as usize * info.height as usize);
+    for line in buffer.chunks(info.line_size).take(info.height as usize) {
+        for pixel in line.chunks(channels * sample_size).take(info.width as usize) {
+            let samples: Vec<u16> = pixel.chunks(sample_size)
+                .map(|sample| match sample {
+                    [high, low] => u16::from_be_bytes([*high, *low]),
+                    [value] => *value as u16 * 257,
+                    _ => 0,
+                })
+                .collect();
+            rgba.push(match (info.color_type, samples.as_slice()) {
+                (ColorType::Grayscale, [v]) => [*v, *v, *v, u16::MAX],
+                (ColorType::GrayscaleAlpha, [v, a]) => [*v, *v, *v, *a],
+                (ColorType::Rgb, [r, g, b]) => [*r, *g, *b, u16::MAX],
+                (ColorType::Rgba, [r, g, b, a]) => [*r, *g, *b, *a],
+                _ => return Err(PngError::PixelError("unexpected colour type".to_string())),
+            });
+        }
+    }
+    Ok(Pixels { width: info.width, height: info.height, rgba })
+}
+
+/// Compares two decoded images pixel by pixel, fully transparent pixels match whatever their colour
+pub fn compare(old: &Pixels, new: &Pixels) -> PixelComparison {
+    if (old.width, old.height) != (new.width, new.height) {
+        return PixelComparison::DifferentSize { old: (old.width, old.height), new: (new.width, new.height) };
+    }
+    let differing = old.rgba.iter()
+        .zip(new.rgba.iter())
+        .filter(|(a, b)| a != b && !(a[3] == 0 && b[3] == 0))
+        .count();
+    match differing {
+        0 => PixelComparison::Identical,
+        differing => PixelComparison::Different { differing, total: old.rgba.len() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32, color_type: ColorType, data: &[u8]) -> Png {
+        let mut bytes = Vec::new();
+        let mut encoder = png_codec::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        Png::try_from(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_grey_matches_rgb() {
+        let grey = decode(&encode(2, 1, ColorType::Grayscale, &[10, 200])).unwrap();
+        let rgb = decode(&encode(2, 1, ColorType::Rgb, &[10, 10, 10, 200, 200, 200])).unwrap();
+        assert_eq!(grey.rgba[1], [200 * 257, 200 * 257, 200 * 257, u16::MAX]);
+        assert_eq!(compare(&grey, &rgb), PixelComparison::Identical);
+    }
+
+    #[test]
+    fn test_different_pixels() {
+        let old = decode(&encode(2, 1, ColorType::Grayscale, &[10, 200])).unwrap();
+        let new = decode(&encode(2, 1, ColorType::Grayscale, &[10, 201])).unwrap();
+        assert_eq!(compare(&old, &new), PixelComparison::Different { differing: 1, total: 2 });
+        let small = decode(&encode(1, 1, ColorType::Grayscale, &[10])).unwrap();
+        assert_eq!(compare(&old, &small), PixelComparison::DifferentSize { old: (2, 1), new: (1, 1) });
+    }
+}
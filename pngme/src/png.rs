@@ -2,53 +2,211 @@ use std::fmt::{Display, Formatter};
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use anyhow::Result;
 
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::{self, ChunkType, TypeIndex};
 use crate::png_error::PngError;
 use crate::utils::read_be_u32;
 
+/// standard ancillary chunk types that may appear at most once in an image
+const SINGLE_TYPES: [&str; 12] =
+    ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs", "tIME", "eXIf", "cICP"];
+
+/// chunk types that must come before PLTE, and before IDAT, in the order the spec requires
+const BEFORE_PLTE: [&str; 8] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI"];
+const BEFORE_IDAT: [&str; 5] = ["bKGD", "hIST", "tRNS", "pHYs", "sPLT"];
+
+/// What `copy_ancillary_from` did with an ancillary chunk of the source image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AncillaryCopy {
+    Copied,
+    /// unsafe to copy and copying those was not asked for
+    Unsafe,
+    /// the image already has this chunk, or its own chunk of a type that may appear only once
+    Present,
+}
+
 #[derive(Debug)]
 pub struct Png {
     signature: [u8; 8],
-    pub(crate) chunks: Vec<Chunk>,
+    chunks: Vec<Chunk>,
+    /// positions of the chunks of each type, built on the first lookup and dropped on any change
+    index: OnceLock<TypeIndex>,
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             signature: Png::STANDARD_HEADER,
             chunks,
+            index: OnceLock::new(),
         }
     }
+    /// Reads a PNG from a file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PngError> {
-        let f = fs::read(path)?;
+        let f = fs::read(path.as_ref())?;
         let png = Png::try_from(&f[..])?;
         Ok(png)
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        self.chunks_mut().push(chunk);
+    }
+    /// Inserts a chunk at `index` in the chunk list, panics if `index` is past the end
+    pub fn insert(&mut self, index: usize, chunk: Chunk) {
+        self.chunks_mut().insert(index, chunk);
+    }
+    /// Inserts chunks in order just before the first IEND, where pngme adds every chunk it writes
+    #[cfg(feature = "cli")]
+    pub(crate) fn insert_before_end<I: IntoIterator<Item = Chunk>>(&mut self, chunks: I) -> Result<(), PngError> {
+        let end = *self.indices_of(&ChunkType::try_from(*b"IEND")?).first().ok_or(PngError::MissingEnd)?;
+        self.chunks_mut().splice(end..end, chunks);
+        Ok(())
+    }
+    /// Removes the chunk at `index` in the chunk list, panics if `index` is out of bounds
+    pub fn remove(&mut self, index: usize) -> Chunk {
+        self.chunks_mut().remove(index)
+    }
+    /// Swaps two chunks in the chunk list, panics if either index is out of bounds
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.chunks_mut().swap(a, b);
+    }
+    /// Keeps only the chunks the predicate returns true for
+    pub fn retain<F: FnMut(&Chunk) -> bool>(&mut self, f: F) {
+        self.chunks_mut().retain(f);
+    }
+    /// Removes in one pass every chunk `f` picks by its position and contents, returning them
+    /// with their position in the chunk list before the removal
+    #[cfg(feature = "cli")]
+    pub(crate) fn drain_where<F: FnMut(usize, &Chunk) -> bool>(&mut self, mut f: F) -> Vec<(usize, Chunk)> {
+        let mut removed = Vec::new();
+        let mut kept = Vec::with_capacity(self.chunks.len());
+        for (index, chunk) in std::mem::take(self.chunks_mut()).into_iter().enumerate() {
+            match f(index, &chunk) {
+                true => removed.push((index, chunk)),
+                false => kept.push(chunk),
+            }
+        }
+        self.chunks = kept;
+        removed
+    }
+    /// Iterates over the chunks mutably, chunks keep their CRC up to date as they change
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Chunk> {
+        self.chunks_mut().iter_mut()
+    }
+    // every change to the chunk list goes through here so the type index never goes stale
+    fn chunks_mut(&mut self) -> &mut Vec<Chunk> {
+        self.index.take();
+        &mut self.chunks
+    }
+    fn type_index(&self) -> &TypeIndex {
+        self.index.get_or_init(|| chunk_type::index_types(self.chunks.iter().map(Chunk::chunk_type)))
+    }
+    /// Positions in the chunk list of the chunks of the given type
+    pub fn indices_of(&self, chunk_type: &ChunkType) -> &[usize] {
+        self.type_index().get(chunk_type).map(Vec::as_slice).unwrap_or_default()
+    }
+    /// Reorders the chunks the way the PNG spec requires: IHDR first, the chunks that must
+    /// precede PLTE and IDAT before them, IDAT chunks together and IEND last. Other chunks
+    /// stay after the chunk they follow, those following IDAT or IEND go between them.
+    pub fn sort_by_spec_order(&mut self) {
+        let mut previous = 0;
+        let mut ranks = Vec::with_capacity(self.chunks.len());
+        for chunk in self.chunks.iter() {
+            let name = chunk.chunk_type().to_string();
+            let rank = match name.as_str() {
+                "IHDR" => 0,
+                _ if BEFORE_PLTE.contains(&name.as_str()) => 1,
+                "PLTE" => 2,
+                _ if BEFORE_IDAT.contains(&name.as_str()) => 3,
+                "IDAT" => 4,
+                "IEND" => 6,
+                _ => match previous {
+                    0 => 1,
+                    4 | 6 => 5,
+                    rank => rank,
+                },
+            };
+            ranks.push(rank);
+            previous = rank;
+        }
+        let mut ranked: Vec<(usize, Chunk)> = ranks.into_iter().zip(self.chunks_mut().drain(..)).collect();
+        // stable, so chunks of the same rank keep their order
+        ranked.sort_by_key(|(rank, _)| *rank);
+        *self.chunks_mut() = ranked.into_iter().map(|(_, chunk)| chunk).collect();
+    }
+    /// Copies the ancillary chunks of another image into this one, keeping their place relative
+    /// to PLTE and IDAT. Chunks marked unsafe to copy depend on the critical chunks of their
+    /// image and are only copied with `copy_unsafe`.
+    pub fn copy_ancillary_from(&mut self, source: &Png, copy_unsafe: bool) -> Vec<(ChunkType, AncillaryCopy)> {
+        let mut res = Vec::new();
+        // which critical chunks the copied chunk has to come before
+        let mut before: &[&str] = &["PLTE", "IDAT", "IEND"];
+        for chunk in source.chunks.iter() {
+            let chunk_type = chunk.chunk_type();
+            match chunk_type.to_string().as_str() {
+                "PLTE" => before = &["IDAT", "IEND"],
+                "IDAT" => before = &["IEND"],
+                _ => {}
+            }
+            if chunk_type.is_critical() {
+                continue;
+            }
+            let present = self.chunks.iter().any(|own| {
+                own.chunk_type() == chunk_type
+                    && (own.data() == chunk.data() || SINGLE_TYPES.contains(&chunk_type.to_string().as_str()))
+            });
+            let outcome = match (present, chunk_type.is_safe_to_copy() || copy_unsafe) {
+                (true, _) => AncillaryCopy::Present,
+                (false, false) => AncillaryCopy::Unsafe,
+                (false, true) => {
+                    let index = self.chunks.iter()
+                        .position(|own| before.contains(&own.chunk_type().to_string().as_str()))
+                        .unwrap_or(self.chunks.len());
+                    self.insert(index, chunk.clone());
+                    AncillaryCopy::Copied
+                }
+            };
+            res.push((chunk_type.clone(), outcome));
+        }
+        res
     }
+    /// Removes the first chunk of the given type, the same one `chunk_by_type` returns
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngError> {
+        self.remove_nth_chunk(chunk_type, 0)
+    }
+    /// Removes the chunk at position `n` among the chunks of the given type
+    pub fn remove_nth_chunk(&mut self, chunk_type: &str, n: usize) -> Result<Chunk, PngError> {
+        let index = self.nth_index(chunk_type, n)?;
+        Ok(self.remove(index))
+    }
+    /// Swaps the data of the chunk at position `n` among the chunks of the given type, keeping
+    /// its place in the file, and returns the chunk it replaced. Length and CRC are recomputed.
+    pub fn replace_chunk_data(&mut self, chunk_type: &str, n: usize, data: Vec<u8>) -> Result<Chunk, PngError> {
+        let index = self.nth_index(chunk_type, n)?;
+        let chunk = Chunk::try_new(self.chunks[index].chunk_type().clone(), data)?;
+        Ok(std::mem::replace(&mut self.chunks[index], chunk))
+    }
+    // index in the chunk list of the chunk at position `n` among the chunks of the given type
+    fn nth_index(&self, chunk_type: &str, n: usize) -> Result<usize, PngError> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        self.indices_of(&chunk_type).get(n).copied().ok_or(PngError::NotFoundChunkType)
+    }
+    /// Removes every chunk of the given type, returning them in file order
+    pub fn remove_chunks_by_type(&mut self, chunk_type: &str) -> Result<Vec<Chunk>, PngError> {
         let remove_type = ChunkType::from_str(chunk_type)?;
-
-        let mut flag = false;
-        let mut remove_index = 0;
-        for i in 0..self.chunks.len() {
-            if *self.chunks[i].chunk_type() == remove_type {
-                flag = true;
-                remove_index = i;
-            }
+        let (removed, kept) = self.chunks_mut().drain(..)
+            .partition(|chunk| *chunk.chunk_type() == remove_type);
+        *self.chunks_mut() = kept;
+        match removed.is_empty() {
+            true => Err(PngError::NotFoundChunkType),
+            false => Ok(removed),
         }
-        return match flag {
-            true => Ok(self.chunks.remove(remove_index)),
-            false => Err(PngError::NotFoundChunkType),
-        };
     }
     pub fn header(&self) -> &[u8; 8] {
         &self.signature
@@ -57,20 +215,31 @@ impl Png {
         &self.chunks
     }
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        for val in self.chunks.iter() {
-            match val.chunk_type().to_string() == chunk_type.to_string() {
-                true => return Some(val),
-                false => continue
-            }
+        self.chunks_by_type(chunk_type).first().copied()
+    }
+    /// Chunks of the given type in file order, looked up in the type index unlike `PngRef`'s
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        match ChunkType::from_str(chunk_type) {
+            Ok(chunk_type) => self.indices_of(&chunk_type).iter().map(|i| &self.chunks[*i]).collect(),
+            Err(_) => Vec::new(),
         }
-        None
     }
+    /// Byte offset of every chunk from the start of the file, in chunk order
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        self.as_png_ref().chunk_offsets()
+    }
+    /// Data of the first chunk of the given type as text, None when it is not UTF-8 such as an
+    /// encoded message
     pub fn data_string_by_type(&self, chunk_type: &str) -> Option<String> {
-        let chunk_data = self.chunk_by_type(chunk_type);
-        return if let Some(data) = chunk_data {
-            let a = data.data_as_string().unwrap();
-            Some(a)
-        } else { None };
+        self.chunk_by_type(chunk_type).and_then(|chunk| chunk.data_as_string().ok())
+    }
+    /// Parses a PNG up to and including IEND, returning whatever follows it unparsed
+    pub fn try_from_until_end(value: &[u8]) -> Result<(Png, &[u8]), PngError> {
+        PngRef::try_from_until_end(value).map(|(png, rest)| (png.to_png(), rest))
+    }
+    /// Borrowed view of the image, the form read-only code takes images in
+    pub fn as_png_ref(&self) -> PngRef<'_> {
+        PngRef { chunks: self.chunks.iter().map(Chunk::as_chunk_ref).collect() }
     }
     pub fn as_bytes(&self) -> Vec<u8> {
         // self.signature.to_vec().iter()
@@ -90,6 +259,54 @@ impl TryFrom<&[u8]> for Png {
     type Error = PngError;
 
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        PngRef::try_from(value).map(|png| png.to_png())
+    }
+}
+
+/// A PNG whose chunks borrow their data from the bytes it was parsed from, for commands that
+/// only read an image. `to_png` copies it into a `Png` that can be changed.
+#[derive(Debug)]
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> PngRef<'a> {
+    /// Parses a PNG up to and including IEND, returning whatever follows it unparsed
+    pub fn try_from_until_end(value: &'a [u8]) -> Result<(PngRef<'a>, &'a [u8]), PngError> {
+        PngRef::parse(value, true)
+    }
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks_by_type(chunk_type).first().copied()
+    }
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&ChunkRef<'a>> {
+        self.chunks.iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == chunk_type.as_bytes())
+            .collect()
+    }
+    /// Byte offset of every chunk from the start of the file, in chunk order
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        let mut offset = Png::STANDARD_HEADER.len();
+        self.chunks.iter()
+            .map(|chunk| {
+                let start = offset;
+                offset += chunk.data().len() + 12;
+                start
+            })
+            .collect()
+    }
+    /// Copies every chunk into an owned `Png`
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect())
+    }
+
+    // with `stop_at_end` parsing ends at IEND and the bytes after it are returned
+    fn parse(value: &'a [u8], stop_at_end: bool) -> Result<(PngRef<'a>, &'a [u8]), PngError> {
+        if value.len() < Png::STANDARD_HEADER.len() {
+            return Err(PngError::ChunkHeaderError);
+        }
         let (header, chunks_bytes) = value.split_at(8);
         // check the header
         match header.to_vec() == Png::STANDARD_HEADER {
@@ -97,13 +314,21 @@ impl TryFrom<&[u8]> for Png {
             false => return Err(PngError::ChunkHeaderError)
         }
         // check each chunk
-        let mut chunk_vec: Vec<Chunk> = Vec::new();
+        let mut chunk_vec: Vec<ChunkRef<'a>> = Vec::new();
         let mut chunks = chunks_bytes;
         loop {
+            // a length running past the end of the file means the file is truncated
+            if chunks.len() < 12 || read_be_u32(chunks) as usize > chunks.len() - 12 {
+                return Err(PngError::ChunkError);
+            }
             let left_len = read_be_u32(chunks) as usize;
             let (left, right) = chunks.split_at(left_len + 12);
-            let temp_chunk = Chunk::try_from(left)?;
+            let temp_chunk = ChunkRef::try_from(left)?;
+            let is_end = temp_chunk.chunk_type().bytes() == *b"IEND";
             chunk_vec.push(temp_chunk);
+            if stop_at_end && is_end {
+                return Ok((PngRef { chunks: chunk_vec }, right));
+            }
             match right.len() {
                 0 => break,
                 x if x < 12 && x > 0 => return Err(PngError::ChunkError),
@@ -112,7 +337,15 @@ impl TryFrom<&[u8]> for Png {
                 }
             }
         }
-        Ok(Png::from_chunks(chunk_vec))
+        Ok((PngRef { chunks: chunk_vec }, &[]))
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PngError;
+
+    fn try_from(value: &'a [u8]) -> std::result::Result<Self, Self::Error> {
+        PngRef::parse(value, false).map(|(png, _)| png)
     }
 }
 
@@ -122,22 +355,45 @@ impl Display for Png {
     }
 }
 
+/// Serialized as its list of chunks, the signature is always the standard one
+#[cfg(feature = "serde")]
+impl serde::Serialize for Png {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Png", 1)?;
+        state.serialize_field("chunks", &self.chunks)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Png {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct PngFields {
+            chunks: Vec<Chunk>,
+        }
+
+        let fields = PngFields::deserialize(deserializer)?;
+        Ok(Png::from_chunks(fields.chunks))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::str::FromStr;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -162,6 +418,131 @@ mod tests {
         assert_eq!(png.chunks().len(), 3);
     }
 
+    #[test]
+    fn test_replace_chunk_data() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("FrSt", "first").unwrap(),
+            chunk_from_strings("miDl", "one").unwrap(),
+            chunk_from_strings("LASt", "last").unwrap(),
+            chunk_from_strings("miDl", "two").unwrap(),
+        ]);
+        let old = png.replace_chunk_data("miDl", 1, b"a longer message".to_vec()).unwrap();
+        assert_eq!(old.data(), b"two");
+
+        let replaced = &png.chunks()[3];
+        assert_eq!(replaced.data(), b"a longer message");
+        assert_eq!(replaced.length(), 16);
+        assert!(replaced.has_valid_crc());
+        assert_eq!(png.chunks()[1].data(), b"one");
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+
+        assert!(matches!(png.replace_chunk_data("miDl", 2, Vec::new()), Err(PngError::NotFoundChunkType)));
+    }
+
+    #[test]
+    fn test_mutation() {
+        let mut png = testing_png();
+        for chunk in png.iter_mut() {
+            chunk.data_mut().extend_from_slice(b"!");
+        }
+        png.insert(1, chunk_from_strings("NeXt", "inserted").unwrap());
+        png.swap(0, 3);
+        png.retain(|chunk| chunk.chunk_type().to_string() != "miDl");
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["LASt", "NeXt", "FrSt"]);
+        assert_eq!(png.chunks()[0].data(), b"I am the last chunk!");
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_png_ref() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let owned = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.chunks().len(), owned.chunks().len());
+        assert_eq!(png.chunk_offsets(), owned.chunk_offsets());
+        assert_eq!(png.chunk_by_type("RuSt").unwrap().data_as_str().unwrap(), "hey");
+        assert_eq!(png.to_png().as_bytes(), PNG_FILE);
+        assert_eq!(owned.as_png_ref().chunks_by_type("RuSt").len(), 1);
+        assert!(PngRef::try_from(&PNG_FILE[..PNG_FILE.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_type_index_follows_changes() {
+        let mut png = testing_png();
+        assert_eq!(png.chunk_by_type("miDl").unwrap().data(), b"I am another chunk");
+        for chunk in png.iter_mut() {
+            chunk.set_chunk_type(ChunkType::from_str("miDl").unwrap());
+        }
+        assert_eq!(png.chunks_by_type("miDl").len(), 3);
+        assert!(png.chunk_by_type("FrSt").is_none());
+        png.remove(0);
+        assert_eq!(png.indices_of(&ChunkType::from_str("miDl").unwrap()), &[0, 1]);
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_drain_where() {
+        let mut png = testing_png();
+        let removed = png.drain_where(|index, chunk| index == 0 || chunk.chunk_type().to_string() == "LASt");
+        let positions: Vec<usize> = removed.iter().map(|(index, _)| *index).collect();
+        assert_eq!(positions, vec![0, 2]);
+        assert_eq!(png.chunks().len(), 1);
+        assert_eq!(png.indices_of(&ChunkType::from_str("miDl").unwrap()), &[0]);
+    }
+
+    #[test]
+    fn test_sort_by_spec_order() {
+        let chunks = ["ruSt", "IDAT", "tEXt", "IHDR", "gAMA", "IEND", "prIv", "PLTE", "IDAT", "tRNS", "tIME"];
+        let mut png = Png::from_chunks(chunks.iter().map(|t| chunk_from_strings(t, "").unwrap()).collect());
+        png.sort_by_spec_order();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec![
+            "IHDR", "ruSt", "gAMA", "PLTE", "tRNS", "tIME", "IDAT", "IDAT", "tEXt", "prIv", "IEND",
+        ]);
+    }
+
+    #[test]
+    fn test_copy_ancillary_from() {
+        let image = |chunks: &[(&str, &str)]| {
+            Png::from_chunks(chunks.iter().map(|(t, data)| chunk_from_strings(t, data).unwrap()).collect())
+        };
+        let source = image(&[
+            ("IHDR", "old"), ("gAMA", "g"), ("sBIT", "s"), ("PLTE", "p"), ("tRNS", "t"),
+            ("IDAT", "x"), ("tEXt", "t"), ("ruSt", "m"), ("IEND", ""),
+        ]);
+        let mut target = image(&[("IHDR", "new"), ("gAMA", "own"), ("PLTE", "p"), ("IDAT", "y"), ("IEND", "")]);
+        let outcomes: Vec<AncillaryCopy> = target.copy_ancillary_from(&source, false)
+            .into_iter()
+            .map(|(_, outcome)| outcome)
+            .collect();
+        assert_eq!(outcomes, vec![
+            AncillaryCopy::Present,
+            AncillaryCopy::Unsafe,
+            AncillaryCopy::Unsafe,
+            AncillaryCopy::Copied,
+            AncillaryCopy::Copied,
+        ]);
+        let types: Vec<String> = target.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "PLTE", "IDAT", "tEXt", "ruSt", "IEND"]);
+
+        target.copy_ancillary_from(&source, true);
+        let types: Vec<String> = target.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "sBIT", "PLTE", "tRNS", "IDAT", "tEXt", "ruSt", "IEND"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let json = serde_json::to_string(&png).unwrap();
+        let decoded: Png = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.as_bytes(), PNG_FILE);
+
+        let toml = toml::to_string(&png).unwrap();
+        assert_eq!(toml::from_str::<Png>(&toml).unwrap().as_bytes(), PNG_FILE);
+    }
+
     #[test]
     fn test_valid_from_bytes() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -234,7 +615,11 @@ mod tests {
         let chunk = png.chunk_by_type("FrSt").unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+        assert_eq!(png.data_string_by_type("FrSt").unwrap(), "I am the first chunk");
 
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("biNa").unwrap(), vec![0x85, 0xff]));
+        assert!(png.data_string_by_type("biNa").is_none());
     }
 
     #[test]
@@ -255,6 +640,83 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunk_removes_first_match() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        let removed = png.remove_chunk("TeSt").unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "first");
+        assert_eq!(&png.chunk_by_type("TeSt").unwrap().data_as_string().unwrap(), "second");
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        let chunks = png.chunks_by_type("TeSt");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[1].data_as_string().unwrap(), "second");
+        assert!(png.chunks_by_type("NoNe").is_empty());
+    }
+
+    #[test]
+    fn test_remove_nth_chunk() {
+        let mut png = testing_png();
+        for message in ["first", "second", "third"] {
+            png.append_chunk(chunk_from_strings("TeSt", message).unwrap());
+        }
+        let removed = png.remove_nth_chunk("TeSt", 1).unwrap();
+        assert_eq!(&removed.data_as_string().unwrap(), "second");
+        assert_eq!(png.chunks_by_type("TeSt").len(), 2);
+        assert!(png.remove_nth_chunk("TeSt", 2).is_err());
+    }
+
+    #[test]
+    fn test_remove_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        let removed = png.remove_chunks_by_type("TeSt").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png.chunks().len(), 3);
+        assert!(png.remove_chunks_by_type("TeSt").is_err());
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offsets = png.chunk_offsets();
+        assert_eq!(offsets.len(), png.chunks().len());
+        assert_eq!(offsets[0], 8);
+        for (offset, chunk) in offsets.iter().zip(png.chunks()) {
+            assert_eq!(&PNG_FILE[offset + 4..offset + 8], chunk.chunk_type().to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_until_end() {
+        let mut bytes = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "hidden").unwrap(),
+        ]).as_bytes();
+        bytes.extend_from_slice(b"garbage");
+        let (png, rest) = Png::try_from_until_end(&bytes).unwrap();
+
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(rest.len(), 18 + 7);
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        assert!(Png::try_from(&PNG_FILE[..4]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..8]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..100]).is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
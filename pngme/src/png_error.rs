@@ -19,6 +19,100 @@ pub enum PngError {
     #[error("there is not such chunkType")]
     NotFoundChunkType,
 
-    #[error("IO error")]
+    #[error("invalid key")]
+    KeyError,
+
+    #[error("invalid encrypted message")]
+    SealedPayloadError,
+
+    #[error("message could not be decrypted with this identity")]
+    DecryptError,
+
+    #[error("message is encrypted, pass --identity to decrypt it")]
+    EncryptedMessage,
+
+    #[error("image has no signature")]
+    NotSigned,
+
+    #[error("invalid signature chunk")]
+    SignatureError,
+
+    #[error("invalid manifest: {0}")]
+    ManifestError(String),
+
+    #[error("could not decode the image: {0}")]
+    PixelError(String),
+
+    #[error("invalid strip policy: {0}")]
+    PolicyError(String),
+
+    #[error("invalid range: {0}, expected start..end within the chunk data")]
+    RangeError(String),
+
+    #[error("image has {0} problems")]
+    ValidationFailed(usize),
+
+    #[error("signature verification failed")]
+    VerificationFailed,
+
+    #[error("invalid compressed message")]
+    CompressionError,
+
+    #[error("decompressed message is larger than {0} bytes")]
+    DecompressionLimit(usize),
+
+    #[error("image has no IEND chunk, run repair first")]
+    MissingEnd,
+
+    #[error("an append to {0} did not finish, run repair to roll it back")]
+    InterruptedAppend(String),
+
+    #[error("message of {0} bytes does not fit in one chunk, use --split")]
+    ChunkTooLarge(usize),
+
+    #[error("invalid piece size {0}")]
+    PieceSizeError(usize),
+
+    #[error("invalid message piece")]
+    PieceError,
+
+    #[error("message is incomplete, missing pieces {missing:?} of {total}")]
+    MissingPieces { missing: Vec<u32>, total: u32 },
+
+    #[error("invalid parity length {0}")]
+    ParityError(u8),
+
+    #[error("message is too damaged to recover")]
+    UnrecoverableError,
+
+    #[error("refusing to write {chunk_type} because {reason}, use --force or a private ancillary type such as {suggestion}")]
+    UnsafeChunkType {
+        chunk_type: String,
+        reason: &'static str,
+        suggestion: String,
+    },
+
+    #[error("refusing to remove critical chunk {0}, use --force")]
+    CriticalChunkRemoval(String),
+
+    #[error("no output file, pass out_path or --in-place")]
+    MissingOutput,
+
+    #[error("out_path cannot be used with several input files, use --out-dir")]
+    BatchOutput,
+
+    #[error("invalid input: {0}")]
+    InputError(String),
+
+    #[error("stdin can only be read once, pass the other input as a file")]
+    StdinTaken,
+
+    #[error("{0} files failed")]
+    BatchFailed(usize),
+
+    #[error("processing the file panicked")]
+    Panicked,
+
+    #[error("IO error: {0}")]
     IOError(#[from] io::Error),
 }
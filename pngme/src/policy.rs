@@ -0,0 +1,135 @@
+use crate::chunk_type::ChunkType;
+use crate::png_error::PngError;
+
+/// Critical chunks defined by the PNG spec, every other critical type makes decoders give up
+pub const STANDARD_CRITICAL_TYPES: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+/// Registered ancillary chunks, readers parse these and a message in one makes it invalid
+const STANDARD_ANCILLARY_TYPES: [&str; 21] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI", "bKGD", "hIST", "tRNS",
+    "eXIf", "pHYs", "sPLT", "tIME", "iTXt", "tEXt", "zTXt", "acTL", "fcTL", "fdAT",
+];
+
+/// Refuses message chunk types that would make standard decoders reject the image, and public
+/// types whose format readers expect to be the registered one
+pub fn check_encode(chunk_type: &ChunkType) -> Result<(), PngError> {
+    let reason = if !chunk_type.is_reserved_bit_valid() {
+        "its reserved bit is set"
+    } else if is_standard_critical(chunk_type) {
+        "it is a standard critical chunk"
+    } else if chunk_type.is_critical() {
+        "it is an unknown critical chunk"
+    } else if STANDARD_ANCILLARY_TYPES.iter().any(|t| t.as_bytes() == chunk_type.bytes()) {
+        "it is a standard ancillary chunk"
+    } else if chunk_type.is_public() {
+        "public chunk types are reserved for registered chunks"
+    } else {
+        return Ok(());
+    };
+    Err(PngError::UnsafeChunkType {
+        chunk_type: chunk_type.to_string(),
+        reason,
+        suggestion: suggest_safe_type(chunk_type).to_string(),
+    })
+}
+
+/// Refuses to remove critical chunks, the image cannot be decoded without them
+pub fn check_remove(chunk_type: &ChunkType) -> Result<(), PngError> {
+    match chunk_type.is_critical() {
+        true => Err(PngError::CriticalChunkRemoval(chunk_type.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Same letters as the given type, with the case set for a private, ancillary, safe-to-copy chunk
+pub fn suggest_safe_type(chunk_type: &ChunkType) -> ChunkType {
+    let [a, b, c, d] = chunk_type.bytes();
+    ChunkType::try_from([
+        a.to_ascii_lowercase(),
+        b.to_ascii_lowercase(),
+        c.to_ascii_uppercase(),
+        d.to_ascii_lowercase(),
+    ])
+    .unwrap()
+}
+
+fn is_standard_critical(chunk_type: &ChunkType) -> bool {
+    STANDARD_CRITICAL_TYPES.iter().any(|t| t.as_bytes() == chunk_type.bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn check(chunk_type: &str) -> Result<(), PngError> {
+        check_encode(&ChunkType::from_str(chunk_type).unwrap())
+    }
+
+    #[test]
+    fn test_encode_private_ancillary() {
+        assert!(check("ruSt").is_ok());
+        assert!(check("ruST").is_ok());
+    }
+
+    #[test]
+    fn test_encode_public_ancillary() {
+        match check("tEXt") {
+            Err(PngError::UnsafeChunkType { reason, suggestion, .. }) => {
+                assert_eq!(reason, "it is a standard ancillary chunk");
+                assert_eq!(suggestion, "teXt");
+            }
+            _ => panic!("expected tEXt to be refused"),
+        }
+        match check("rUSt") {
+            Err(PngError::UnsafeChunkType { reason, .. }) => {
+                assert_eq!(reason, "public chunk types are reserved for registered chunks");
+            }
+            _ => panic!("expected rUSt to be refused"),
+        }
+    }
+
+    #[test]
+    fn test_encode_standard_critical() {
+        match check("IHDR") {
+            Err(PngError::UnsafeChunkType { reason, suggestion, .. }) => {
+                assert_eq!(reason, "it is a standard critical chunk");
+                assert_eq!(suggestion, "ihDr");
+            }
+            _ => panic!("expected IHDR to be refused"),
+        }
+    }
+
+    #[test]
+    fn test_encode_unknown_critical() {
+        match check("RuST") {
+            Err(PngError::UnsafeChunkType { reason, suggestion, .. }) => {
+                assert_eq!(reason, "it is an unknown critical chunk");
+                assert_eq!(suggestion, "ruSt");
+            }
+            _ => panic!("expected RuST to be refused"),
+        }
+    }
+
+    #[test]
+    fn test_encode_reserved_bit() {
+        assert!(matches!(check("rust"), Err(PngError::UnsafeChunkType { .. })));
+    }
+
+    #[test]
+    fn test_remove() {
+        assert!(check_remove(&ChunkType::from_str("IDAT").unwrap()).is_err());
+        assert!(check_remove(&ChunkType::from_str("RuSt").unwrap()).is_err());
+        assert!(check_remove(&ChunkType::from_str("ruSt").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_suggestion_is_safe() {
+        for chunk_type in ["IHDR", "RuST", "rust", "ABCD"] {
+            let suggestion = suggest_safe_type(&ChunkType::from_str(chunk_type).unwrap());
+            assert!(check_encode(&suggestion).is_ok());
+            assert!(!suggestion.is_public());
+            assert!(suggestion.is_safe_to_copy());
+        }
+    }
+}
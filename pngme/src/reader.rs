@@ -0,0 +1,147 @@
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::{self, ChunkType, TypeIndex};
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::stdio;
+
+/// Anything a `ChunkReader` can read from
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Where a chunk is in the file and what it holds, read from its header alone
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkEntry {
+    pub offset: u64,
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Random access to the chunks of a PNG file. Opening it reads only the signature and the
+/// header of every chunk, seeking over the data, and chunks are read one at a time on demand.
+pub struct ChunkReader<R> {
+    reader: BufReader<R>,
+    entries: Vec<ChunkEntry>,
+    index: TypeIndex,
+}
+
+impl ChunkReader<Box<dyn ReadSeek>> {
+    /// Opens a file, or reads stdin into memory when the path is `-`
+    pub fn open(path: &Path) -> Result<Self, PngError> {
+        let reader: Box<dyn ReadSeek> = match stdio::is_stdio(path) {
+            true => Box::new(Cursor::new(stdio::read(path)?)),
+            false => Box::new(stdio::open(path)?),
+        };
+        ChunkReader::new(reader)
+    }
+}
+
+impl<R: Read + Seek> ChunkReader<R> {
+    /// Checks the signature and walks the chunk headers, failing on a truncated chunk
+    pub fn new(reader: R) -> Result<Self, PngError> {
+        let mut reader = BufReader::new(reader);
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature).map_err(|_| PngError::ChunkHeaderError)?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(PngError::ChunkHeaderError);
+        }
+        let mut entries = Vec::new();
+        let mut offset = signature.len() as u64;
+        while offset < len {
+            let mut header = [0; 8];
+            reader.read_exact(&mut header).map_err(|_| PngError::ChunkError)?;
+            let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+            let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&header[4..]).unwrap())?;
+            // a length running past the end of the file means the file is truncated
+            if offset + length as u64 + 12 > len {
+                return Err(PngError::ChunkError);
+            }
+            entries.push(ChunkEntry { offset, length, chunk_type });
+            // a relative seek over the data and CRC keeps what is buffered of the headers after it
+            reader.seek_relative(length as i64 + 4)?;
+            offset += length as u64 + 12;
+        }
+        let index = chunk_type::index_types(entries.iter().map(|entry| &entry.chunk_type));
+        Ok(ChunkReader { reader, entries, index })
+    }
+
+    pub fn entries(&self) -> &[ChunkEntry] {
+        &self.entries
+    }
+
+    /// Positions in `entries` of the chunks of the given type
+    pub fn indices_of(&self, chunk_type: &ChunkType) -> &[usize] {
+        self.index.get(chunk_type).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Position in `entries` of the chunk starting at a byte offset
+    pub fn position_at(&self, offset: u64) -> Option<usize> {
+        self.entries.binary_search_by_key(&offset, |entry| entry.offset).ok()
+    }
+
+    /// Reads one chunk, checking its CRC
+    pub fn read_chunk(&mut self, position: usize) -> Result<Chunk, PngError> {
+        Chunk::try_from(self.read_bytes(position)?.as_slice())
+    }
+
+    /// Reads one chunk, keeping its stored CRC even if it does not match
+    pub fn read_chunk_unchecked(&mut self, position: usize) -> Result<Chunk, PngError> {
+        Chunk::try_from_unchecked(&self.read_bytes(position)?)
+    }
+
+    fn read_bytes(&mut self, position: usize) -> Result<Vec<u8>, PngError> {
+        let entry = self.entries.get(position).ok_or(PngError::NotFoundChunkType)?;
+        let mut res = vec![0; entry.length as usize + 12];
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        self.reader.read_exact(&mut res)?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_file() -> Vec<u8> {
+        let chunks = [("IHDR", "header"), ("ruSt", "first"), ("IDAT", "pixels"), ("ruSt", "second"), ("IEND", "")]
+            .iter()
+            .map(|(chunk_type, data)| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec()))
+            .collect();
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_entries() {
+        let reader = ChunkReader::new(Cursor::new(testing_file())).unwrap();
+        let offsets: Vec<u64> = reader.entries().iter().map(|entry| entry.offset).collect();
+        assert_eq!(offsets, vec![8, 26, 43, 61, 79]);
+        assert_eq!(reader.indices_of(&ChunkType::from_str("ruSt").unwrap()), &[1, 3]);
+        assert!(reader.indices_of(&ChunkType::from_str("tEXt").unwrap()).is_empty());
+        assert_eq!(reader.position_at(61), Some(3));
+        assert_eq!(reader.position_at(62), None);
+    }
+
+    #[test]
+    fn test_read_chunk() {
+        let mut bytes = testing_file();
+        bytes[70] ^= 1;
+        let mut reader = ChunkReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.read_chunk(1).unwrap().data(), b"first");
+        assert!(matches!(reader.read_chunk(3), Err(PngError::CRCError)));
+        assert_eq!(reader.read_chunk_unchecked(3).unwrap().data(), b"sdcond");
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let bytes = testing_file();
+        let result = ChunkReader::new(Cursor::new(&bytes[..bytes.len() - 1]));
+        assert!(matches!(result, Err(PngError::ChunkError)));
+        assert!(matches!(ChunkReader::new(Cursor::new(b"not a png")), Err(PngError::ChunkHeaderError)));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::chunk::{Chunk, ChunkRef};
+use crate::frame;
+use crate::png_error::PngError;
+use crate::validate::Problem;
+
+/// A chunk as it appears in the JSON output of every command
+#[derive(Debug, Serialize)]
+pub struct ChunkRecord {
+    /// position of the chunk in the file, starting at 0
+    pub index: usize,
+    /// byte offset of the chunk's length field from the start of the file
+    pub offset: usize,
+    /// length of the chunk data
+    pub length: usize,
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    pub crc: u32,
+    pub crc_valid: bool,
+}
+
+impl ChunkRecord {
+    pub fn new(index: usize, offset: usize, chunk: &ChunkRef) -> ChunkRecord {
+        let chunk_type = chunk.chunk_type();
+        ChunkRecord {
+            index,
+            offset,
+            length: chunk.data().len(),
+            chunk_type: chunk_type.to_string(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            crc: chunk.crc(),
+            crc_valid: chunk.has_valid_crc(),
+        }
+    }
+}
+
+/// Outermost pngme encoding recorded in the frame of a chunk's data
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadKind {
+    SplitPiece,
+    ErrorCorrected,
+    Encrypted,
+    Compressed,
+    Text,
+    Binary,
+}
+
+impl PayloadKind {
+    pub fn of(data: &[u8]) -> PayloadKind {
+        let body = match frame::read(data) {
+            Some((frame, _)) if frame.split => return PayloadKind::SplitPiece,
+            Some((frame, _)) if frame.error_corrected => return PayloadKind::ErrorCorrected,
+            Some((frame, _)) if frame.sealed => return PayloadKind::Encrypted,
+            Some((frame, _)) if frame.compressed => return PayloadKind::Compressed,
+            Some((_, body)) => body,
+            None => data,
+        };
+        match std::str::from_utf8(body) {
+            Ok(_) => PayloadKind::Text,
+            Err(_) => PayloadKind::Binary,
+        }
+    }
+}
+
+impl Display for PayloadKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            PayloadKind::SplitPiece => "split message piece",
+            PayloadKind::ErrorCorrected => "error corrected message",
+            PayloadKind::Encrypted => "encrypted message",
+            PayloadKind::Compressed => "compressed message",
+            PayloadKind::Text => "text",
+            PayloadKind::Binary => "binary data",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// `print --format json`
+#[derive(Debug, Serialize)]
+pub struct PrintRecord<'a> {
+    pub file: &'a Path,
+    pub chunks: Vec<ChunkRecord>,
+}
+
+/// `decode --format json`, messages is empty when there is no message of the chunk type
+#[derive(Debug, Serialize)]
+pub struct DecodeRecord<'a> {
+    pub file: &'a Path,
+    pub chunk_type: &'a str,
+    pub messages: Vec<MessageRecord>,
+}
+
+/// One decoded message
+#[derive(Debug, Serialize)]
+pub struct MessageRecord {
+    /// position among the chunks of the same type, as taken by --index
+    pub index: usize,
+    pub offset: usize,
+    /// length of the chunk data, before any decoding
+    pub length: usize,
+    /// bytes repaired by error correction
+    pub corrected: usize,
+    /// the decoded message if it is valid UTF-8
+    pub text: Option<String>,
+    /// the decoded message in hex
+    pub hex: String,
+}
+
+impl MessageRecord {
+    pub fn new(index: usize, offset: usize, chunk: &Chunk, data: &[u8], corrected: usize) -> MessageRecord {
+        MessageRecord {
+            index,
+            offset,
+            length: chunk.data().len(),
+            corrected,
+            text: String::from_utf8(data.to_vec()).ok(),
+            hex: hex::encode(data),
+        }
+    }
+}
+
+/// `remove --format json`, with the removed chunks as they were in the input file
+#[derive(Debug, Serialize)]
+pub struct RemoveRecord<'a> {
+    pub file: &'a Path,
+    pub output: &'a Path,
+    pub chunk_type: &'a str,
+    pub removed: Vec<ChunkRecord>,
+}
+
+/// `strip --format json`, with the removed chunks as they were in the input file
+#[derive(Debug, Serialize)]
+pub struct StripRecord<'a> {
+    pub file: &'a Path,
+    pub output: &'a Path,
+    pub removed: Vec<ChunkRecord>,
+    /// bytes after IEND that were dropped
+    pub trailing_bytes: usize,
+}
+
+/// `scan --format json`
+#[derive(Debug, Serialize)]
+pub struct ScanRecord<'a> {
+    pub file: &'a Path,
+    pub messages: Vec<ScanHit>,
+}
+
+/// A chunk that may hold a hidden message
+#[derive(Debug, Serialize)]
+pub struct ScanHit {
+    #[serde(flatten)]
+    pub chunk: ChunkRecord,
+    pub payload: PayloadKind,
+    pub after_iend: bool,
+}
+
+/// `validate --format json`
+#[derive(Debug, Serialize)]
+pub struct ValidateRecord<'a> {
+    pub file: &'a Path,
+    pub valid: bool,
+    pub chunks: Vec<ChunkRecord>,
+    pub problems: &'a [Problem],
+}
+
+/// Writes the record as a single line of JSON, so several records form NDJSON
+pub fn write_json<T: Serialize>(out: &mut dyn Write, record: &T) -> Result<(), PngError> {
+    serde_json::to_writer(&mut *out, record).map_err(io::Error::from)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::frame::Frame;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_record_schema() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec());
+        let mut out = Vec::new();
+        write_json(&mut out, &ChunkRecord::new(2, 33, &chunk.as_chunk_ref())).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(out.iter().filter(|b| **b == b'\n').count(), 1);
+        assert_eq!(value["index"], 2);
+        assert_eq!(value["offset"], 33);
+        assert_eq!(value["length"], 2);
+        assert_eq!(value["type"], "ruSt");
+        assert_eq!(value["critical"], false);
+        assert_eq!(value["public"], false);
+        assert_eq!(value["reserved_bit_valid"], true);
+        assert_eq!(value["safe_to_copy"], true);
+        assert_eq!(value["crc"], chunk.crc());
+        assert_eq!(value["crc_valid"], true);
+    }
+
+    #[test]
+    fn test_payload_kind_of() {
+        let compressed = Frame { compressed: true, ..Frame::default() };
+        assert_eq!(PayloadKind::of(&compressed.wrap(b"\x01\x02")), PayloadKind::Compressed);
+        assert_eq!(PayloadKind::of(&Frame::default().wrap(b"pmZ\x01 plain")), PayloadKind::Text);
+        assert_eq!(PayloadKind::of(b"pmR1 plain"), PayloadKind::Text);
+        assert_eq!(PayloadKind::of(&[0xff, 0xfe]), PayloadKind::Binary);
+    }
+
+    #[test]
+    fn test_payload_kind_names() {
+        assert_eq!(serde_json::to_string(&PayloadKind::SplitPiece).unwrap(), "\"split_piece\"");
+        assert_eq!(PayloadKind::SplitPiece.to_string(), "split message piece");
+    }
+}
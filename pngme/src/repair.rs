@@ -0,0 +1,252 @@
+use crate::chunk::{Chunk, CRC_32_ISO};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::utils::read_be_u32;
+
+const SIGNATURE: [u8; 8] = Png::STANDARD_HEADER;
+
+/// A change `repair` made to a file
+#[derive(Debug)]
+pub struct Fix {
+    /// byte offset in the original file the fix applies to, if it belongs to a place in the file
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+/// The repaired image and every fix applied to it, in the order they were made
+#[derive(Debug)]
+pub struct Repair {
+    pub png: Png,
+    pub fixes: Vec<Fix>,
+    /// bytes of the original file that could not be read as chunks and are gone
+    pub dropped: usize,
+}
+
+impl Repair {
+    fn fix(&mut self, offset: Option<usize>, message: String) {
+        self.fixes.push(Fix { offset, message });
+    }
+}
+
+/// Rebuilds a damaged PNG file: recomputes bad CRCs, corrects length fields when exactly one
+/// length gives a matching CRC, drops bytes that cannot be read as chunks up to the next intact
+/// chunk, moves chunks found after IEND before it, moves IHDR to the front and appends a missing
+/// IEND
+pub fn repair(bytes: &[u8]) -> Result<Repair, PngError> {
+    let mut res = Repair { png: Png::from_chunks(Vec::new()), fixes: Vec::new(), dropped: 0 };
+    if !bytes.starts_with(&SIGNATURE) {
+        // only trust a damaged signature when an IHDR follows it
+        match bytes.get(12..16) {
+            Some(b"IHDR") => res.fix(Some(0), "replaced a damaged PNG signature".to_string()),
+            _ => return Err(PngError::ChunkHeaderError),
+        }
+    }
+    let chunks = read_chunks(bytes, &mut res);
+    reorder(chunks, &mut res);
+    Ok(res)
+}
+
+// every chunk that can be read, with its offset, fixing lengths and CRCs on the way
+fn read_chunks(bytes: &[u8], res: &mut Repair) -> Vec<(usize, Chunk)> {
+    let mut chunks = Vec::new();
+    let mut offset = SIGNATURE.len();
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let chunk_type = rest.get(4..8)
+            .and_then(|bytes| ChunkType::try_from(<[u8; 4]>::try_from(bytes).unwrap()).ok());
+        let length = match (rest.len(), &chunk_type) {
+            (len, Some(_)) if len >= 12 => find_length(rest),
+            _ => None,
+        };
+        let (chunk_type, length) = match (chunk_type, length) {
+            (Some(chunk_type), Some(length)) => (chunk_type, length),
+            _ => {
+                let skipped = (1..rest.len()).find(|start| is_intact_chunk(&rest[*start..])).unwrap_or(rest.len());
+                res.fix(Some(offset), format!("dropped {} bytes that are not a chunk", skipped));
+                res.dropped += skipped;
+                offset += skipped;
+                continue;
+            }
+        };
+        let stated = read_be_u32(rest) as usize;
+        if length != stated {
+            res.fix(Some(offset), format!("{} length {} corrected to {}", chunk_type, stated, length));
+        }
+        let data = rest[8..length + 8].to_vec();
+        let stored_crc = read_be_u32(&rest[length + 8..]);
+        let chunk = Chunk::new(chunk_type, data);
+        if chunk.crc() != stored_crc {
+            res.fix(
+                Some(offset),
+                format!("{} CRC {:08x} corrected to {:08x}", chunk.chunk_type(), stored_crc, chunk.crc()),
+            );
+        }
+        chunks.push((offset, chunk));
+        offset += length + 12;
+    }
+    chunks
+}
+
+// the stated length when it leads to the end of the file or to another chunk, otherwise the
+// only length whose stored CRC matches the data, otherwise the stated length if its CRC matches
+fn find_length(rest: &[u8]) -> Option<usize> {
+    let stated = read_be_u32(rest) as usize;
+    if stated <= rest.len() - 12 && next_is_chunk(&rest[stated + 12..]) {
+        return Some(stated);
+    }
+    let mut digest = CRC_32_ISO.digest();
+    digest.update(&rest[4..8]);
+    let mut candidates = Vec::new();
+    for length in 0..=rest.len() - 12 {
+        if digest.clone().finalize() == read_be_u32(&rest[length + 8..]) && next_is_chunk(&rest[length + 12..]) {
+            candidates.push(length);
+        }
+        digest.update(&rest[length + 8..length + 9]);
+    }
+    match candidates.as_slice() {
+        [length] => Some(*length),
+        // the chunk itself is whole, the damage starts after it
+        [] if is_intact_chunk(rest) => Some(stated),
+        _ => None,
+    }
+}
+
+// a chunk type, a length that fits the file and a matching CRC, where reading picks up again
+// after bytes that are not a chunk
+fn is_intact_chunk(rest: &[u8]) -> bool {
+    if rest.len() < 12 || !rest[4..8].iter().all(u8::is_ascii_alphabetic) {
+        return false;
+    }
+    let length = read_be_u32(rest) as usize;
+    if length > rest.len() - 12 {
+        return false;
+    }
+    CRC_32_ISO.checksum(&rest[4..length + 8]) == read_be_u32(&rest[length + 8..])
+}
+
+// too short to be a chunk counts too, those bytes get dropped on the next read
+fn next_is_chunk(next: &[u8]) -> bool {
+    next.len() < 12 || next[4..8].iter().all(u8::is_ascii_alphabetic)
+}
+
+// puts IHDR first and IEND last, adding IEND when it is missing
+fn reorder(chunks: Vec<(usize, Chunk)>, res: &mut Repair) {
+    let is_type = |chunk: &Chunk, name: &str| chunk.chunk_type().to_string() == name;
+    let mut ordered: Vec<Chunk> = Vec::new();
+    let mut end = None;
+    for (offset, chunk) in chunks {
+        match (is_type(&chunk, "IEND"), &end) {
+            (true, None) => end = Some(chunk),
+            (true, Some(_)) => res.fix(Some(offset), "dropped a second IEND".to_string()),
+            (false, Some(_)) => {
+                res.fix(Some(offset), format!("moved {} from after IEND to before it", chunk.chunk_type()));
+                ordered.push(chunk);
+            }
+            (false, None) => ordered.push(chunk),
+        }
+    }
+    let header = ordered.iter().position(|chunk| is_type(chunk, "IHDR"));
+    if let Some(index) = header.filter(|index| *index > 0) {
+        res.fix(None, format!("moved IHDR from chunk {} to the start", index));
+        let header = ordered.remove(index);
+        ordered.insert(0, header);
+    }
+    let end = end.unwrap_or_else(|| {
+        res.fix(None, "appended a missing IEND".to_string());
+        Chunk::new(ChunkType::try_from(*b"IEND").unwrap(), Vec::new())
+    });
+    ordered.push(end);
+    res.png = Png::from_chunks(ordered);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    fn testing_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        SIGNATURE.iter().copied().chain(chunks.concat()).collect()
+    }
+
+    fn repaired(bytes: &[u8]) -> (Vec<String>, Vec<String>) {
+        let res = repair(bytes).unwrap();
+        assert!(validate::validate(&res.png.as_bytes()).is_valid());
+        let types = res.png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        (types, res.fixes.into_iter().map(|fix| fix.message).collect())
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let bytes = testing_file(&[chunk("IHDR", &[0; 13]), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let res = repair(&bytes).unwrap();
+        assert!(res.fixes.is_empty());
+        assert_eq!(res.png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_crc_and_missing_end() {
+        let mut idat = chunk("IDAT", b"x");
+        idat[9] ^= 1;
+        let (types, fixes) = repaired(&testing_file(&[chunk("IHDR", &[0; 13]), idat]));
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(fixes.len(), 2);
+        assert!(fixes[0].starts_with("IDAT CRC"));
+        assert_eq!(fixes[1], "appended a missing IEND");
+    }
+
+    #[test]
+    fn test_chunks_after_end() {
+        let bytes = testing_file(&[
+            chunk("IDAT", b"x"),
+            chunk("IHDR", &[0; 13]),
+            chunk("IEND", b""),
+            chunk("ruSt", b"secret"),
+            chunk("IEND", b""),
+        ]);
+        let (types, fixes) = repaired(&bytes);
+        assert_eq!(types, vec!["IHDR", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(fixes, vec![
+            "moved ruSt from after IEND to before it",
+            "dropped a second IEND",
+            "moved IHDR from chunk 1 to the start",
+        ]);
+    }
+
+    #[test]
+    fn test_length_fix() {
+        let mut text = chunk("tEXt", b"Title\0pngme");
+        text[3] = 200;
+        let bytes = testing_file(&[chunk("IHDR", &[0; 13]), text, chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let (types, fixes) = repaired(&bytes);
+        assert_eq!(types, vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(fixes, vec!["tEXt length 200 corrected to 11"]);
+    }
+
+    #[test]
+    fn test_unreadable_bytes() {
+        let mut bytes = testing_file(&[chunk("IHDR", &[0; 13]), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        bytes.extend_from_slice(b"\0\0\0\x01junk");
+        bytes[0] = 0;
+        let (types, fixes) = repaired(&bytes);
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(fixes, vec!["replaced a damaged PNG signature", "dropped 8 bytes that are not a chunk"]);
+        assert!(matches!(repair(b"not a png at all"), Err(PngError::ChunkHeaderError)));
+    }
+
+    #[test]
+    fn test_resync_after_unreadable_bytes() {
+        let garbage = b"\x01\x02\x03\x04\x05\x06\x07\x08\t\n".to_vec();
+        let bytes = testing_file(&[chunk("IHDR", &[0; 13]), garbage, chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let res = repair(&bytes).unwrap();
+        assert_eq!(res.dropped, 10);
+        let (types, fixes) = repaired(&bytes);
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(fixes, vec!["dropped 10 bytes that are not a chunk"]);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto::{first_key_line, parse_key};
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::stdio;
+
+/**
+signature chunk layout:
+magic(4) | public key(32) | type count(u16) | types(4 each) | entry count(u32) | entries(40 each) | signature(64)
+each entry is chunk type(4) | occurrence of that type(u32) | sha256 of type and data(32)
+the signature covers everything before it
+ */
+pub const SIGNATURE_CHUNK_TYPE: &str = "pmSG";
+
+const MAGIC: &[u8; 4] = b"pmS1";
+const KEY_LEN: usize = 32;
+const DIGEST_LEN: usize = 32;
+const ENTRY_LEN: usize = 4 + 4 + DIGEST_LEN;
+const SIGNATURE_LEN: usize = 64;
+const CRITICAL_TYPES: [[u8; 4]; 3] = [*b"IHDR", *b"PLTE", *b"IDAT"];
+
+const PUBLIC_KEY_PREFIX: &str = "pngme-sig-pk-";
+const SECRET_KEY_PREFIX: &str = "pngme-sig-sk-";
+
+/// An Ed25519 key used to sign images, written as `pngme-sig-sk-<hex>`
+pub struct SigningIdentity(SigningKey);
+
+/// The public half of a `SigningIdentity`, written as `pngme-sig-pk-<hex>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignerKey(VerifyingKey);
+
+/// A chunk identified by its type and its position among chunks of that type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkId {
+    chunk_type: [u8; 4],
+    occurrence: u32,
+}
+
+/// Outcome of checking an image against its signature chunk
+#[derive(Debug)]
+pub struct Verification {
+    pub signer: SignerKey,
+    /// the image was signed by the trusted key rather than by whoever re-signed it
+    pub trusted: bool,
+    pub signature_valid: bool,
+    pub added: Vec<ChunkId>,
+    pub removed: Vec<ChunkId>,
+    pub modified: Vec<ChunkId>,
+}
+
+struct Manifest {
+    signer: SignerKey,
+    types: Vec<[u8; 4]>,
+    entries: Vec<(ChunkId, [u8; DIGEST_LEN])>,
+}
+
+impl SigningIdentity {
+    pub fn generate() -> SigningIdentity {
+        SigningIdentity(SigningKey::generate(&mut OsRng))
+    }
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SigningIdentity, PngError> {
+        let text = stdio::read_to_string(path.as_ref())?;
+        SigningIdentity::from_str(first_key_line(&text)?)
+    }
+    pub fn signer_key(&self) -> SignerKey {
+        SignerKey(self.0.verifying_key())
+    }
+    /// Contents of a signing key file, with the public key kept as a comment for reference
+    pub fn to_file_contents(&self) -> String {
+        format!("# created by pngme keygen --signing\n# public key: {}\n{}\n", self.signer_key(), self)
+    }
+}
+
+impl SignerKey {
+    /// Parses a public key given either inline or as the path of a key file
+    pub fn from_arg(arg: &str) -> Result<SignerKey, PngError> {
+        if arg.starts_with(PUBLIC_KEY_PREFIX) {
+            return SignerKey::from_str(arg);
+        }
+        let text = stdio::read_to_string(Path::new(arg))?;
+        SignerKey::from_str(first_key_line(&text)?)
+    }
+}
+
+impl Verification {
+    /// True when the trusted key made an intact signature and no signed chunk changed
+    pub fn is_valid(&self) -> bool {
+        self.trusted && self.signature_valid && self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl FromStr for SigningIdentity {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, PngError> {
+        let bytes = parse_key(s, SECRET_KEY_PREFIX)?;
+        Ok(SigningIdentity(SigningKey::from_bytes(&bytes)))
+    }
+}
+
+impl FromStr for SignerKey {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, PngError> {
+        let bytes = parse_key(s, PUBLIC_KEY_PREFIX)?;
+        let key = VerifyingKey::from_bytes(&bytes).map_err(|_| PngError::KeyError)?;
+        Ok(SignerKey(key))
+    }
+}
+
+impl Display for SigningIdentity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", SECRET_KEY_PREFIX, hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl Display for SignerKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", PUBLIC_KEY_PREFIX, hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl ChunkId {
+    pub fn new(chunk_type: &ChunkType, occurrence: u32) -> ChunkId {
+        ChunkId { chunk_type: chunk_type.bytes(), occurrence }
+    }
+}
+
+impl Display for ChunkId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", String::from_utf8_lossy(&self.chunk_type), self.occurrence)
+    }
+}
+
+/// Builds a signature chunk covering the critical chunks and the chunks of the given message types
+pub fn sign(png: &Png, identity: &SigningIdentity, message_types: &[ChunkType]) -> Result<Chunk, PngError> {
+    let mut types = CRITICAL_TYPES.to_vec();
+    for chunk_type in message_types {
+        if !types.contains(&chunk_type.bytes()) {
+            types.push(chunk_type.bytes());
+        }
+    }
+    let manifest = Manifest {
+        signer: identity.signer_key(),
+        entries: digest_chunks(png, &types),
+        types,
+    };
+    let mut data = manifest.to_bytes();
+    let signature = identity.0.sign(&data);
+    data.extend_from_slice(&signature.to_bytes());
+    Ok(Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK_TYPE)?, data))
+}
+
+/// Checks the signature chunk of an image and reports which signed chunks changed since signing.
+/// The signer key is stored in the chunk, so anyone can sign a modified image again with their own
+/// key; only a signature made by the `trusted` key proves where the image came from.
+pub fn verify(png: &Png, trusted: &SignerKey) -> Result<Verification, PngError> {
+    let chunk = png.chunk_by_type(SIGNATURE_CHUNK_TYPE).ok_or(PngError::NotSigned)?;
+    let data = chunk.data();
+    if data.len() < SIGNATURE_LEN {
+        return Err(PngError::SignatureError);
+    }
+    let (signed, signature) = data.split_at(data.len() - SIGNATURE_LEN);
+    let manifest = Manifest::try_from(signed)?;
+    let signature = Signature::from_slice(signature).map_err(|_| PngError::SignatureError)?;
+    let signature_valid = manifest.signer.0.verify_strict(signed, &signature).is_ok();
+
+    let signed_digests: HashMap<ChunkId, [u8; DIGEST_LEN]> = manifest.entries.iter().copied().collect();
+    let current = digest_chunks(png, &manifest.types);
+    let current_digests: HashMap<ChunkId, [u8; DIGEST_LEN]> = current.iter().copied().collect();
+
+    let mut verification = Verification {
+        signer: manifest.signer,
+        trusted: manifest.signer == *trusted,
+        signature_valid,
+        added: Vec::new(),
+        removed: Vec::new(),
+        modified: Vec::new(),
+    };
+    for (id, digest) in manifest.entries.iter() {
+        match current_digests.get(id) {
+            None => verification.removed.push(*id),
+            Some(current) if current != digest => verification.modified.push(*id),
+            Some(_) => {}
+        }
+    }
+    for (id, _) in current.iter() {
+        if !signed_digests.contains_key(id) {
+            verification.added.push(*id);
+        }
+    }
+    Ok(verification)
+}
+
+// digest every chunk whose type is in `types`, in file order
+fn digest_chunks(png: &Png, types: &[[u8; 4]]) -> Vec<(ChunkId, [u8; DIGEST_LEN])> {
+    let mut occurrences: HashMap<[u8; 4], u32> = HashMap::new();
+    let mut entries = Vec::new();
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type().bytes();
+        if !types.contains(&chunk_type) {
+            continue;
+        }
+        let occurrence = occurrences.entry(chunk_type).or_insert(0);
+        let digest = Sha256::new()
+            .chain_update(chunk_type)
+            .chain_update(chunk.data())
+            .finalize();
+        entries.push((ChunkId { chunk_type, occurrence: *occurrence }, digest.into()));
+        *occurrence += 1;
+    }
+    entries
+}
+
+impl Manifest {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut res = MAGIC.to_vec();
+        res.extend_from_slice(self.signer.0.as_bytes());
+        res.extend_from_slice(&(self.types.len() as u16).to_be_bytes());
+        for chunk_type in self.types.iter() {
+            res.extend_from_slice(chunk_type);
+        }
+        res.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for (id, digest) in self.entries.iter() {
+            res.extend_from_slice(&id.chunk_type);
+            res.extend_from_slice(&id.occurrence.to_be_bytes());
+            res.extend_from_slice(digest);
+        }
+        res
+    }
+}
+
+impl TryFrom<&[u8]> for Manifest {
+    type Error = PngError;
+
+    fn try_from(value: &[u8]) -> Result<Self, PngError> {
+        let mut reader = value;
+        if take(&mut reader, MAGIC.len())? != MAGIC {
+            return Err(PngError::SignatureError);
+        }
+        let key_bytes: [u8; KEY_LEN] = take(&mut reader, KEY_LEN)?.try_into().unwrap();
+        let signer = VerifyingKey::from_bytes(&key_bytes).map_err(|_| PngError::SignatureError)?;
+        let type_count = u16::from_be_bytes(take(&mut reader, 2)?.try_into().unwrap());
+        let mut types = Vec::new();
+        for _ in 0..type_count {
+            types.push(take(&mut reader, 4)?.try_into().unwrap());
+        }
+        let entry_count = u32::from_be_bytes(take(&mut reader, 4)?.try_into().unwrap());
+        if reader.len() != entry_count as usize * ENTRY_LEN {
+            return Err(PngError::SignatureError);
+        }
+        let entries = reader
+            .chunks(ENTRY_LEN)
+            .map(|entry| {
+                let id = ChunkId {
+                    chunk_type: entry[0..4].try_into().unwrap(),
+                    occurrence: u32::from_be_bytes(entry[4..8].try_into().unwrap()),
+                };
+                (id, entry[8..].try_into().unwrap())
+            })
+            .collect();
+        Ok(Manifest {
+            signer: SignerKey(signer),
+            types,
+            entries,
+        })
+    }
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], PngError> {
+    if reader.len() < len {
+        return Err(PngError::SignatureError);
+    }
+    let (head, tail) = reader.split_at(len);
+    *reader = tail;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            chunk_from_strings("IDAT", "first pixels"),
+            chunk_from_strings("IDAT", "more pixels"),
+            chunk_from_strings("tEXt", "unsigned comment"),
+            chunk_from_strings("ruSt", "hidden message"),
+            chunk_from_strings("IEND", ""),
+        ])
+    }
+
+    fn signed_png(identity: &SigningIdentity) -> Png {
+        let mut png = testing_png();
+        let message_types = [ChunkType::from_str("ruSt").unwrap()];
+        let signature = sign(&png, identity, &message_types).unwrap();
+        png.insert_before_end([signature]).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_signed_image_is_valid() {
+        let png = signed_png(&SigningIdentity::generate());
+        assert!(validate::validate(&png.as_bytes()).is_valid());
+    }
+
+    #[test]
+    fn test_verify_untouched_image() {
+        let identity = SigningIdentity::generate();
+        let png = signed_png(&identity);
+        let verification = verify(&png, &identity.signer_key()).unwrap();
+
+        assert!(verification.is_valid());
+        assert_eq!(verification.signer, identity.signer_key());
+    }
+
+    #[test]
+    fn test_verify_signed_again_by_another_key() {
+        let identity = SigningIdentity::generate();
+        let mut png = signed_png(&identity);
+        png.remove_chunks_by_type(SIGNATURE_CHUNK_TYPE).unwrap();
+        png.insert_before_end([chunk_from_strings("ruSt", "forged message")]).unwrap();
+        let forger = SigningIdentity::generate();
+        let signature = sign(&png, &forger, &[ChunkType::from_str("ruSt").unwrap()]).unwrap();
+        png.insert_before_end([signature]).unwrap();
+        let verification = verify(&png, &identity.signer_key()).unwrap();
+
+        assert!(verification.signature_valid);
+        assert!(!verification.trusted);
+        assert!(!verification.is_valid());
+        assert!(verify(&png, &forger.signer_key()).unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_verify_ignores_unsigned_chunks() {
+        let identity = SigningIdentity::generate();
+        let mut png = signed_png(&identity);
+        png.remove_chunk("tEXt").unwrap();
+        png.insert_before_end([chunk_from_strings("zzZz", "new unsigned chunk")]).unwrap();
+
+        assert!(verify(&png, &identity.signer_key()).unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_verify_reports_changes() {
+        let identity = SigningIdentity::generate();
+        let png = signed_png(&identity);
+        let mut chunks: Vec<Chunk> = Vec::new();
+        for chunk in png.chunks() {
+            match chunk.chunk_type().to_string().as_str() {
+                "ruSt" => chunks.push(chunk_from_strings("ruSt", "altered message")),
+                "IDAT" if chunks.iter().any(|c| c.chunk_type().to_string() == "IDAT") => {}
+                _ => chunks.push(Chunk::try_from(chunk.as_bytes().as_ref()).unwrap()),
+            }
+        }
+        chunks.insert(chunks.len() - 1, chunk_from_strings("ruSt", "second message"));
+        let verification = verify(&Png::from_chunks(chunks), &identity.signer_key()).unwrap();
+
+        assert!(verification.signature_valid);
+        assert!(!verification.is_valid());
+        assert_eq!(verification.removed.iter().map(|id| id.to_string()).collect::<Vec<_>>(), ["IDAT#1"]);
+        assert_eq!(verification.modified.iter().map(|id| id.to_string()).collect::<Vec<_>>(), ["ruSt#0"]);
+        assert_eq!(verification.added.iter().map(|id| id.to_string()).collect::<Vec<_>>(), ["ruSt#1"]);
+    }
+
+    #[test]
+    fn test_verify_forged_manifest() {
+        let identity = SigningIdentity::generate();
+        let png = signed_png(&identity);
+        let chunks = png.chunks().iter()
+            .map(|chunk| {
+                let mut data = chunk.data().to_vec();
+                if chunk.chunk_type().to_string() == SIGNATURE_CHUNK_TYPE {
+                    data[MAGIC.len() + KEY_LEN + 4] ^= 1;
+                }
+                Chunk::new(ChunkType::try_from(chunk.chunk_type().bytes()).unwrap(), data)
+            })
+            .collect();
+
+        assert!(!verify(&Png::from_chunks(chunks), &identity.signer_key()).unwrap().signature_valid);
+    }
+
+    #[test]
+    fn test_verify_unsigned_image() {
+        let signer = SigningIdentity::generate().signer_key();
+        assert!(matches!(verify(&testing_png(), &signer), Err(PngError::NotSigned)));
+    }
+
+    #[test]
+    fn test_signing_key_text_round_trip() {
+        let identity = SigningIdentity::generate();
+        let parsed = SigningIdentity::from_str(&identity.to_string()).unwrap();
+        assert_eq!(parsed.signer_key(), identity.signer_key());
+
+        let signer = SignerKey::from_arg(&identity.signer_key().to_string()).unwrap();
+        assert_eq!(signer, identity.signer_key());
+    }
+}
@@ -0,0 +1,199 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+
+use crate::chunk::MAX_DATA_LENGTH;
+use crate::png_error::PngError;
+
+/**
+message piece layout:
+magic(4) | message id(8) | index(u32) | total(u32) | piece of the payload
+ */
+const MAGIC: &[u8; 4] = b"pmP1";
+const ID_LEN: usize = 8;
+pub const HEADER_LEN: usize = MAGIC.len() + ID_LEN + 4 + 4;
+
+/// Returns true if the data looks like one of the pieces produced by `split`
+pub fn is_piece(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && data.starts_with(MAGIC)
+}
+
+/// Returns true for every piece but the first, so a split message is only listed once
+pub fn is_continuation(data: &[u8]) -> bool {
+    is_piece(data) && read_u32(&data[MAGIC.len() + ID_LEN..]) > 0
+}
+
+/// The id every piece of the same message shares, the data must be a piece
+pub fn message_id(piece: &[u8]) -> &[u8] {
+    &piece[MAGIC.len()..MAGIC.len() + ID_LEN]
+}
+
+/// Splits a payload into pieces of at most `max_chunk_size` bytes each, headers included
+pub fn split(payload: &[u8], max_chunk_size: usize) -> Result<Vec<Vec<u8>>, PngError> {
+    if max_chunk_size <= HEADER_LEN || max_chunk_size > MAX_DATA_LENGTH {
+        return Err(PngError::PieceSizeError(max_chunk_size));
+    }
+    let piece_size = max_chunk_size - HEADER_LEN;
+    let total = payload.len().div_ceil(piece_size).max(1);
+    let total = u32::try_from(total).map_err(|_| PngError::PieceSizeError(max_chunk_size))?;
+    let mut id = [0u8; ID_LEN];
+    OsRng.fill_bytes(&mut id);
+
+    let mut pieces: Vec<&[u8]> = payload.chunks(piece_size).collect();
+    if pieces.is_empty() {
+        pieces.push(&[]);
+    }
+    let res = pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut data = MAGIC.to_vec();
+            data.extend_from_slice(&id);
+            data.extend_from_slice(&(index as u32).to_be_bytes());
+            data.extend_from_slice(&total.to_be_bytes());
+            data.extend_from_slice(piece);
+            data
+        })
+        .collect();
+    Ok(res)
+}
+
+/// Joins the pieces of the message `first` belongs to, in index order. `pieces` holds every
+/// piece of the chunk type, `first` included.
+pub fn reassemble<'a, I>(first: &[u8], pieces: I) -> Result<Vec<u8>, PngError>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    if !is_piece(first) {
+        return Err(PngError::PieceError);
+    }
+    let id = message_id(first);
+    let total = read_u32(&first[MAGIC.len() + ID_LEN + 4..]);
+    let pieces: Vec<&[u8]> = pieces.into_iter().filter(|data| is_piece(data)).collect();
+    // the total comes from the file, refuse one the chunks could never fill before allocating
+    if total == 0 || total as usize > pieces.len() {
+        return Err(PngError::PieceError);
+    }
+
+    let mut slots: Vec<Option<&[u8]>> = vec![None; total as usize];
+    for data in pieces {
+        if message_id(data) != id {
+            continue;
+        }
+        let index = read_u32(&data[MAGIC.len() + ID_LEN..]);
+        let piece_total = read_u32(&data[MAGIC.len() + ID_LEN + 4..]);
+        if piece_total != total || index >= total {
+            return Err(PngError::PieceError);
+        }
+        // two pieces claiming the same place cannot both be right
+        let slot = &mut slots[index as usize];
+        if slot.is_some() {
+            return Err(PngError::PieceError);
+        }
+        *slot = Some(&data[HEADER_LEN..]);
+    }
+
+    let missing: Vec<u32> = slots.iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.is_none())
+        .map(|(index, _)| index as u32)
+        .collect();
+    if !missing.is_empty() {
+        return Err(PngError::MissingPieces { missing, total });
+    }
+    Ok(slots.into_iter().flatten().flatten().copied().collect())
+}
+
+fn read_u32(input: &[u8]) -> u32 {
+    u32::from_be_bytes(input[..4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_sizes() {
+        let pieces = split(&testing_payload(), 100).unwrap();
+        assert_eq!(pieces.len(), 13);
+        assert!(pieces.iter().all(|piece| piece.len() <= 100 && is_piece(piece)));
+        assert!(!is_continuation(&pieces[0]));
+        assert!(pieces[1..].iter().all(|piece| is_continuation(piece)));
+        assert!(pieces.iter().all(|piece| message_id(piece) == message_id(&pieces[0])));
+    }
+
+    #[test]
+    fn test_split_reassemble() {
+        let payload = testing_payload();
+        let pieces = split(&payload, 100).unwrap();
+        let actual = reassemble(&pieces[0], pieces.iter().rev().map(|piece| piece.as_slice())).unwrap();
+        assert_eq!(actual, payload);
+    }
+
+    #[test]
+    fn test_split_empty_payload() {
+        let pieces = split(&[], 100).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert!(reassemble(&pieces[0], pieces.iter().map(|piece| piece.as_slice())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_too_small() {
+        assert!(split(&testing_payload(), HEADER_LEN).is_err());
+    }
+
+    #[test]
+    fn test_reassemble_missing_pieces() {
+        let pieces = split(&testing_payload(), 100).unwrap();
+        let other = split(b"another message sharing the chunk type", 30).unwrap();
+        let result = reassemble(
+            &pieces[0],
+            pieces.iter()
+                .enumerate()
+                .filter(|(index, _)| *index != 3 && *index != 7)
+                .map(|(_, piece)| piece)
+                .chain(other.iter())
+                .map(|piece| piece.as_slice()),
+        );
+        match result {
+            Err(PngError::MissingPieces { missing, total }) => {
+                assert_eq!(missing, vec![3, 7]);
+                assert_eq!(total, 13);
+            }
+            _ => panic!("expected missing pieces"),
+        }
+    }
+
+    #[test]
+    fn test_reassemble_ignores_other_messages() {
+        let first = split(b"first message", 30).unwrap();
+        let second = split(b"second message", 30).unwrap();
+        let actual = reassemble(
+            &second[0],
+            first.iter().chain(second.iter()).map(|piece| piece.as_slice()),
+        ).unwrap();
+        assert_eq!(actual, b"second message");
+    }
+
+    #[test]
+    fn test_reassemble_crafted_total() {
+        let mut piece = split(b"message", 100).unwrap().remove(0);
+        piece[MAGIC.len() + ID_LEN + 4..HEADER_LEN].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(reassemble(&piece, [piece.as_slice()]), Err(PngError::PieceError)));
+    }
+
+    #[test]
+    fn test_reassemble_duplicate_index() {
+        let pieces = split(&testing_payload(), 100).unwrap();
+        let mut duplicate = pieces[2].clone();
+        duplicate[HEADER_LEN] ^= 1;
+        let result = reassemble(
+            &pieces[0],
+            pieces.iter().chain([&duplicate]).map(|piece| piece.as_slice()),
+        );
+        assert!(matches!(result, Err(PngError::PieceError)));
+    }
+}
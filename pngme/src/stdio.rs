@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use memmap2::Mmap;
+
+use crate::atomic;
+use crate::png_error::PngError;
+
+/// Path that stands for stdin when reading and stdout when writing
+pub const STDIO_PATH: &str = "-";
+
+/// Files smaller than this are read by `map` instead, mapping only pays off for large files
+const MAP_THRESHOLD: u64 = 1 << 20;
+
+static STDIN_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the path is `-`
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Opens a file for reading, failing while an append to it is unfinished
+pub fn open(path: &Path) -> Result<File, PngError> {
+    if atomic::journal_path(path).exists() {
+        return Err(PngError::InterruptedAppend(path.display().to_string()));
+    }
+    Ok(File::open(path)?)
+}
+
+/// Reads the whole file, or all of stdin for `-`. Stdin can only be read once per run.
+pub fn read(path: &Path) -> Result<Vec<u8>, PngError> {
+    if !is_stdio(path) {
+        let mut res = Vec::new();
+        open(path)?.read_to_end(&mut res)?;
+        return Ok(res);
+    }
+    if STDIN_TAKEN.swap(true, Ordering::SeqCst) {
+        return Err(PngError::StdinTaken);
+    }
+    let mut res = Vec::new();
+    io::stdin().lock().read_to_end(&mut res)?;
+    Ok(res)
+}
+
+/// Contents of a file opened with `map`
+pub enum Contents {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(map) => map,
+            Contents::Read(bytes) => bytes,
+        }
+    }
+}
+
+/// Memory maps a large file so it can be parsed without copying it, smaller files and stdin
+/// are read as with `read`.
+///
+/// Known limitation: a mapped file must not be changed in place while pngme reads it. Another
+/// process writing to it breaks the guarantee that the `&[u8]` over the map does not change,
+/// which is undefined behavior, and truncating it makes reads fault. pngme replaces files by
+/// renaming a new file over them, which leaves an existing map untouched.
+pub fn map(path: &Path) -> Result<Contents, PngError> {
+    if is_stdio(path) {
+        return Ok(Contents::Read(read(path)?));
+    }
+    let mut file = open(path)?;
+    if file.metadata()?.len() < MAP_THRESHOLD {
+        let mut res = Vec::new();
+        file.read_to_end(&mut res)?;
+        return Ok(Contents::Read(res));
+    }
+    // SAFETY: not sound against other processes changing the file, see the known limitation
+    // above. Within pngme only `append` writes a file in place, and it reads that file through
+    // its own handle rather than a map.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(Contents::Mapped(map))
+}
+
+/// Same as `read` for text files
+pub fn read_to_string(path: &Path) -> Result<String, PngError> {
+    String::from_utf8(read(path)?).map_err(|err| PngError::InputError(err.to_string()))
+}
+
+/// Writes the contents to stdout for `-`, otherwise atomically replaces the file
+pub fn write(path: &Path, contents: &[u8], backup: bool) -> Result<(), PngError> {
+    if !is_stdio(path) {
+        return atomic::write(path, contents, backup);
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(contents)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+        assert!(!is_stdio(Path::new("image.png")));
+    }
+
+    #[test]
+    fn test_read_and_write_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message.txt");
+        write(&path, b"hidden", false).unwrap();
+        assert_eq!(read(&path).unwrap(), b"hidden");
+        assert_eq!(read_to_string(&path).unwrap(), "hidden");
+        assert_eq!(&*map(&path).unwrap(), b"hidden");
+
+        let empty = dir.path().join("empty.png");
+        fs::write(&empty, b"").unwrap();
+        assert!(map(&empty).unwrap().is_empty());
+        assert!(matches!(map(&path).unwrap(), Contents::Read(_)));
+
+        let large = dir.path().join("large.png");
+        fs::write(&large, vec![7; MAP_THRESHOLD as usize]).unwrap();
+        let contents = map(&large).unwrap();
+        assert!(matches!(contents, Contents::Mapped(_)));
+        assert_eq!(contents.len(), MAP_THRESHOLD as usize);
+    }
+
+    #[test]
+    fn test_unfinished_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        fs::write(&path, b"image").unwrap();
+        fs::write(atomic::journal_path(&path), b"journal").unwrap();
+        assert!(matches!(read(&path), Err(PngError::InterruptedAppend(_))));
+        assert!(matches!(map(&path), Err(PngError::InterruptedAppend(_))));
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::png_error::PngError;
+use crate::stdio;
+
+const TEXT_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+const EXIF_TYPES: [&str; 1] = ["eXIf"];
+
+/// Groups of ancillary chunks that can be stripped together
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// every ancillary chunk
+    AllAncillary,
+    /// private ancillary chunks, where pngme and other tools hide data
+    PrivateOnly,
+    /// tEXt, zTXt and iTXt
+    Text,
+    /// eXIf
+    Exif,
+}
+
+/// Which chunks `strip` removes. Critical chunks are never removed, and chunk types in `keep`
+/// survive even if a preset or `remove` matches them.
+#[derive(Debug, Default)]
+pub struct StripPolicy {
+    pub presets: Vec<Preset>,
+    pub keep: Vec<ChunkType>,
+    pub remove: Vec<ChunkType>,
+}
+
+// layout of a policy file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    presets: Vec<Preset>,
+    #[serde(default)]
+    keep: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+impl StripPolicy {
+    /// Checks the chunk types, refusing to remove critical ones
+    pub fn new(presets: Vec<Preset>, keep: &[String], remove: &[String]) -> Result<StripPolicy, PngError> {
+        let keep = parse_types(keep)?;
+        let remove = parse_types(remove)?;
+        if let Some(critical) = remove.iter().find(|chunk_type| chunk_type.is_critical()) {
+            return Err(PngError::PolicyError(format!("{} is a critical chunk and cannot be stripped", critical)));
+        }
+        Ok(StripPolicy { presets, keep, remove })
+    }
+
+    /// Reads a TOML policy file, for example
+    /// ```toml
+    /// presets = ["all-ancillary"]
+    /// keep = ["gAMA", "sRGB", "iCCP", "pHYs"]
+    /// ```
+    pub fn from_file(path: &Path) -> Result<StripPolicy, PngError> {
+        let text = stdio::read_to_string(path)?;
+        let file: PolicyFile = toml::from_str(&text).map_err(|err| PngError::PolicyError(err.to_string()))?;
+        StripPolicy::new(file.presets, &file.keep, &file.remove)
+    }
+
+    /// Adds the presets and chunk types of another policy to this one
+    pub fn merge(&mut self, other: StripPolicy) {
+        self.presets.extend(other.presets);
+        self.keep.extend(other.keep);
+        self.remove.extend(other.remove);
+    }
+
+    /// True when nothing would be removed but trailing data
+    pub fn is_empty(&self) -> bool {
+        self.presets.is_empty() && self.remove.is_empty()
+    }
+
+    pub fn should_remove(&self, chunk_type: &ChunkType) -> bool {
+        if chunk_type.is_critical() || self.keep.contains(chunk_type) {
+            return false;
+        }
+        self.remove.contains(chunk_type) || self.presets.iter().any(|preset| preset.matches(chunk_type))
+    }
+}
+
+impl Preset {
+    fn matches(&self, chunk_type: &ChunkType) -> bool {
+        let name = chunk_type.to_string();
+        match self {
+            Preset::AllAncillary => !chunk_type.is_critical(),
+            Preset::PrivateOnly => !chunk_type.is_critical() && !chunk_type.is_public(),
+            Preset::Text => TEXT_TYPES.contains(&name.as_str()),
+            Preset::Exif => EXIF_TYPES.contains(&name.as_str()),
+        }
+    }
+}
+
+/// Removes every chunk the policy matches, returning them with their index in the original chunk list
+pub fn strip(png: &mut Png, policy: &StripPolicy) -> Vec<(usize, Chunk)> {
+    png.drain_where(|_, chunk| policy.should_remove(chunk.chunk_type()))
+}
+
+fn parse_types(types: &[String]) -> Result<Vec<ChunkType>, PngError> {
+    types.iter().map(|chunk_type| ChunkType::from_str(chunk_type)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunks = ["IHDR", "gAMA", "tEXt", "eXIf", "IDAT", "ruSt", "IEND"]
+            .iter()
+            .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), b"data".to_vec()))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    fn stripped(policy: &StripPolicy) -> (Vec<String>, Vec<String>) {
+        let mut png = testing_png();
+        let removed = strip(&mut png, policy);
+        let names = |chunks: Vec<&Chunk>| chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        (names(png.chunks().iter().collect()), names(removed.iter().map(|(_, chunk)| chunk).collect()))
+    }
+
+    #[test]
+    fn test_presets() {
+        let policy = StripPolicy::new(vec![Preset::AllAncillary], &["gAMA".to_string()], &[]).unwrap();
+        assert_eq!(stripped(&policy).0, vec!["IHDR", "gAMA", "IDAT", "IEND"]);
+
+        let policy = StripPolicy::new(vec![Preset::PrivateOnly], &[], &[]).unwrap();
+        assert_eq!(stripped(&policy).1, vec!["ruSt"]);
+
+        let policy = StripPolicy::new(vec![Preset::Text, Preset::Exif], &[], &[]).unwrap();
+        assert_eq!(stripped(&policy).1, vec!["tEXt", "eXIf"]);
+    }
+
+    #[test]
+    fn test_strip_indices() {
+        let policy = StripPolicy::new(vec![], &[], &["tEXt".to_string(), "ruSt".to_string()]).unwrap();
+        let mut png = testing_png();
+        let removed = strip(&mut png, &policy);
+        let indices: Vec<usize> = removed.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![2, 5]);
+    }
+
+    #[test]
+    fn test_refuses_critical() {
+        let result = StripPolicy::new(vec![], &[], &["IDAT".to_string()]);
+        assert!(matches!(result, Err(PngError::PolicyError(_))));
+    }
+
+    #[test]
+    fn test_policy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, "presets = [\"all-ancillary\"]\nkeep = [\"gAMA\", \"ruSt\"]\n").unwrap();
+        let policy = StripPolicy::from_file(&path).unwrap();
+        assert_eq!(stripped(&policy).0, vec!["IHDR", "gAMA", "IDAT", "ruSt", "IEND"]);
+
+        std::fs::write(&path, "preset = \"text\"\n").unwrap();
+        assert!(matches!(StripPolicy::from_file(&path), Err(PngError::PolicyError(_))));
+    }
+}
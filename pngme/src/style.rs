@@ -0,0 +1,16 @@
+pub const PLAIN: &str = "";
+pub const BOLD: &str = "1";
+pub const RED: &str = "31";
+pub const GREEN: &str = "32";
+pub const YELLOW: &str = "33";
+pub const BLUE: &str = "34";
+pub const MAGENTA: &str = "35";
+pub const CYAN: &str = "36";
+
+/// Wraps the text in an ANSI colour code when `color` is set
+pub fn paint(text: &str, style: &str, color: bool) -> String {
+    match color && !style.is_empty() {
+        true => format!("\x1b[{}m{}\x1b[0m", style, text),
+        false => text.to_string(),
+    }
+}
@@ -0,0 +1,246 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::chunk::ChunkRef;
+use crate::compression::DEFAULT_MAX_OUTPUT;
+use crate::records::PayloadKind;
+use crate::signature::SIGNATURE_CHUNK_TYPE;
+
+const MAX_TEXT_LEN: usize = 40;
+
+/// One line description of a chunk's contents, decoded for the chunk types the PNG spec
+/// defines and pngme's own chunks, and by payload kind for private chunks
+pub fn summarize(chunk: &ChunkRef) -> String {
+    let data = chunk.data();
+    let summary = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => ihdr(data),
+        "PLTE" => Some(format!("{} palette entries", data.len() / 3)),
+        "IDAT" => Some("image data".to_string()),
+        "IEND" => Some("end of image".to_string()),
+        "tEXt" => text(data),
+        "zTXt" => split_keyword(data).map(|(keyword, _)| format!("{}: (compressed text)", clean(keyword))),
+        "iTXt" => international_text(data),
+        "gAMA" => read_u32(data, 0).map(|gamma| format!("gamma {:.5}", gamma as f64 / 100000.0)),
+        "sRGB" => data.first().map(|intent| format!("sRGB, {} rendering intent", rendering_intent(*intent))),
+        "pHYs" => physical_size(data),
+        "tIME" => time(data),
+        "tRNS" => Some(format!("{} transparency bytes", data.len())),
+        "bKGD" => Some(format!("background {}", hex::encode(data))),
+        SIGNATURE_CHUNK_TYPE => Some("pngme signature".to_string()),
+        _ if !chunk.chunk_type().is_public() => Some(PayloadKind::of(data).to_string()),
+        _ => None,
+    };
+    summary.unwrap_or_default()
+}
+
+/// The named fields of the chunk types the PNG spec defines, None for other types or
+/// malformed data. Compressed text is inflated.
+pub fn fields(chunk: &ChunkRef) -> Option<Vec<(&'static str, String)>> {
+    let data = chunk.data();
+    let res = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" if data.len() == 13 => vec![
+            ("width", read_u32(data, 0)?.to_string()),
+            ("height", read_u32(data, 4)?.to_string()),
+            ("bit depth", data[8].to_string()),
+            ("colour type", data[9].to_string()),
+            ("compression", data[10].to_string()),
+            ("filter", data[11].to_string()),
+            ("interlace", data[12].to_string()),
+        ],
+        "tEXt" => {
+            let (keyword, text) = split_keyword(data)?;
+            vec![("keyword", latin1(keyword)), ("text", latin1(text))]
+        }
+        "zTXt" => {
+            let (keyword, rest) = split_keyword(data)?;
+            vec![("keyword", latin1(keyword)), ("text", latin1(&inflate(rest.get(1..)?)?))]
+        }
+        "iTXt" => {
+            let (keyword, rest) = split_keyword(data)?;
+            let compressed = *rest.first()? != 0;
+            let (language, rest) = split_keyword(rest.get(2..)?)?;
+            let (translated, text) = split_keyword(rest)?;
+            let text = match compressed {
+                true => inflate(text)?,
+                false => text.to_vec(),
+            };
+            vec![
+                ("keyword", latin1(keyword)),
+                ("language", latin1(language)),
+                ("translated keyword", String::from_utf8_lossy(translated).to_string()),
+                ("text", String::from_utf8_lossy(&text).to_string()),
+            ]
+        }
+        "gAMA" => vec![("gamma", read_u32(data, 0)?.to_string())],
+        "sRGB" => vec![("rendering intent", rendering_intent(*data.first()?).to_string())],
+        "pHYs" => vec![
+            ("x", read_u32(data, 0)?.to_string()),
+            ("y", read_u32(data, 4)?.to_string()),
+            ("unit", data.get(8)?.to_string()),
+        ],
+        "tIME" => vec![("modified", time(data)?.trim_start_matches("modified ").to_string())],
+        "bKGD" => vec![("background", hex::encode(data))],
+        "PLTE" => vec![("entries", (data.len() / 3).to_string()), ("palette", hex::encode(data))],
+        _ => return None,
+    };
+    Some(res)
+}
+
+fn ihdr(data: &[u8]) -> Option<String> {
+    if data.len() != 13 {
+        return None;
+    }
+    let color = match data[9] {
+        0 => "greyscale",
+        2 => "truecolour",
+        3 => "indexed",
+        4 => "greyscale with alpha",
+        6 => "truecolour with alpha",
+        _ => "unknown colour type",
+    };
+    let interlace = match data[12] {
+        0 => "",
+        _ => ", interlaced",
+    };
+    Some(format!("{}x{}, {}-bit {}{}", read_u32(data, 0)?, read_u32(data, 4)?, data[8], color, interlace))
+}
+
+fn text(data: &[u8]) -> Option<String> {
+    let (keyword, text) = split_keyword(data)?;
+    Some(format!("{}: {}", clean(keyword), clean(text)))
+}
+
+fn international_text(data: &[u8]) -> Option<String> {
+    let (keyword, rest) = split_keyword(data)?;
+    let compressed = *rest.first()? != 0;
+    // skip the compression flag and method, then the language tag and translated keyword
+    let (_, rest) = split_keyword(rest.get(2..)?)?;
+    let (_, text) = split_keyword(rest)?;
+    match compressed {
+        true => Some(format!("{}: (compressed text)", clean(keyword))),
+        false => Some(format!("{}: {}", clean(keyword), clean(text))),
+    }
+}
+
+fn physical_size(data: &[u8]) -> Option<String> {
+    let (x, y) = (read_u32(data, 0)?, read_u32(data, 4)?);
+    match data.get(8)? {
+        1 => Some(format!("{}x{} pixels per metre", x, y)),
+        _ => Some(format!("{}:{} pixel aspect ratio", x, y)),
+    }
+}
+
+fn time(data: &[u8]) -> Option<String> {
+    if data.len() != 7 {
+        return None;
+    }
+    let year = u16::from_be_bytes([data[0], data[1]]);
+    Some(format!(
+        "modified {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, data[2], data[3], data[4], data[5], data[6]
+    ))
+}
+
+fn rendering_intent(intent: u8) -> &'static str {
+    match intent {
+        0 => "perceptual",
+        1 => "relative colorimetric",
+        2 => "saturation",
+        3 => "absolute colorimetric",
+        _ => "unknown",
+    }
+}
+
+// text chunks start with a null terminated keyword
+fn split_keyword(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|b| *b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+// printable and short enough for a table cell
+fn clean(text: &[u8]) -> String {
+    let text: String = String::from_utf8_lossy(text)
+        .chars()
+        .map(|c| if c.is_control() { '.' } else { c })
+        .collect();
+    match text.chars().count() > MAX_TEXT_LEN {
+        true => format!("{}...", text.chars().take(MAX_TEXT_LEN).collect::<String>()),
+        false => text,
+    }
+}
+
+// text chunk keywords and tEXt/zTXt text are Latin-1
+fn latin1(text: &[u8]) -> String {
+    text.iter().map(|b| *b as char).collect()
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut res = Vec::new();
+    ZlibDecoder::new(data).take(DEFAULT_MAX_OUTPUT as u64).read_to_end(&mut res).ok()?;
+    Some(res)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn summary(chunk_type: &str, data: &[u8]) -> String {
+        summarize(&Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_chunk_ref())
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let data = [0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 1];
+        assert_eq!(summary("IHDR", &data), "256x200, 8-bit truecolour with alpha, interlaced");
+        assert_eq!(summary("IHDR", &data[..5]), "");
+    }
+
+    #[test]
+    fn test_text_chunks() {
+        assert_eq!(summary("tEXt", b"Title\0hello\nworld"), "Title: hello.world");
+        assert_eq!(summary("iTXt", b"Title\0\0\0en\0Titel\0hallo"), "Title: hallo");
+        assert_eq!(summary("zTXt", b"Title\0\0xyz"), "Title: (compressed text)");
+        let long = [b"Comment\0".as_slice(), &[b'a'; 60]].concat();
+        assert_eq!(summary("tEXt", &long), format!("Comment: {}...", "a".repeat(40)));
+    }
+
+    #[test]
+    fn test_ancillary_chunks() {
+        assert_eq!(summary("gAMA", &45455u32.to_be_bytes()), "gamma 0.45455");
+        assert_eq!(summary("pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1]), "2835x2835 pixels per metre");
+        assert_eq!(summary("tIME", &[7, 232, 2, 29, 12, 30, 5]), "modified 2024-02-29 12:30:05");
+        assert_eq!(summary("sRGB", &[0]), "sRGB, perceptual rendering intent");
+    }
+
+    #[test]
+    fn test_fields() {
+        let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+        let ihdr = fields(&chunk("IHDR", &[0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 1]).as_chunk_ref()).unwrap();
+        assert_eq!(ihdr[0], ("width", "256".to_string()));
+        assert_eq!(ihdr[6], ("interlace", "1".to_string()));
+
+        let mut compressed = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut compressed, b"caf\xe9").unwrap();
+        let data = [b"Title\0\0".as_slice(), &compressed.finish().unwrap()].concat();
+        let ztxt = fields(&chunk("zTXt", &data).as_chunk_ref()).unwrap();
+        assert_eq!(ztxt, vec![("keyword", "Title".to_string()), ("text", "caf\u{e9}".to_string())]);
+
+        assert!(fields(&chunk("IHDR", &[0; 5]).as_chunk_ref()).is_none());
+        assert!(fields(&chunk("ruSt", b"hi").as_chunk_ref()).is_none());
+    }
+
+    #[test]
+    fn test_private_chunks() {
+        assert_eq!(summary("ruSt", b"hello"), "text");
+        assert_eq!(summary("ruSt", &[0xff, 0xfe]), "binary data");
+        assert_eq!(summary("cHRM", &[0; 32]), "");
+    }
+}
@@ -0,0 +1,198 @@
+use serde::Serialize;
+
+use crate::chunk::ChunkRef;
+use crate::png::Png;
+use crate::utils::read_be_u32;
+
+const SIGNATURE: [u8; 8] = Png::STANDARD_HEADER;
+const IHDR_LENGTH: usize = 13;
+
+/// Something wrong with the structure of a PNG file
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    /// byte offset the problem was found at, if it belongs to a place in the file
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+/// Result of checking a file: every chunk that could be read, with its offset, and every problem found
+#[derive(Debug, Default)]
+pub struct Validation<'a> {
+    pub chunks: Vec<(usize, ChunkRef<'a>)>,
+    pub problems: Vec<Problem>,
+}
+
+impl Validation<'_> {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn problem(&mut self, offset: Option<usize>, message: String) {
+        self.problems.push(Problem { offset, message });
+    }
+}
+
+/// Checks the raw bytes of a PNG file without giving up at the first problem: the signature,
+/// every chunk's length and CRC, the chunk order the spec requires and data after IEND
+pub fn validate(bytes: &[u8]) -> Validation<'_> {
+    let mut res = Validation::default();
+    if !bytes.starts_with(&SIGNATURE) {
+        res.problem(Some(0), "missing PNG signature".to_string());
+        return res;
+    }
+    let mut offset = SIGNATURE.len();
+    let mut after_end = false;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let length = match rest.len() {
+            len if len < 12 => None,
+            len => Some(read_be_u32(rest) as usize).filter(|length| *length <= len - 12),
+        };
+        let chunk = length.and_then(|length| ChunkRef::try_from_unchecked(&rest[..length + 12]).ok());
+        let chunk = match (chunk, after_end) {
+            (Some(chunk), _) => chunk,
+            // whatever follows IEND is not part of the image
+            (None, true) => {
+                res.problem(Some(offset), format!("{} bytes after IEND", rest.len()));
+                break;
+            }
+            (None, false) => {
+                let message = match length {
+                    None if rest.len() < 12 => format!("truncated chunk, only {} bytes left", rest.len()),
+                    None => format!("chunk length {} runs past the end of the file", read_be_u32(rest)),
+                    Some(_) => "invalid chunk type".to_string(),
+                };
+                res.problem(Some(offset), message);
+                match length {
+                    Some(length) => {
+                        offset += length + 12;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        if after_end {
+            res.problem(Some(offset), format!("{} after IEND", chunk.chunk_type()));
+        }
+        if !chunk.has_valid_crc() {
+            res.problem(Some(offset), format!("CRC mismatch in {}", chunk.chunk_type()));
+        }
+        if !chunk.chunk_type().is_reserved_bit_valid() {
+            res.problem(Some(offset), format!("{} has its reserved bit set", chunk.chunk_type()));
+        }
+        after_end |= chunk.chunk_type().to_string() == "IEND";
+        let next = offset + chunk.data().len() + 12;
+        res.chunks.push((offset, chunk));
+        offset = next;
+    }
+    check_order(&mut res);
+    res
+}
+
+// the ordering rules of the PNG spec that every decoder relies on
+fn check_order(res: &mut Validation) {
+    let types: Vec<(usize, String)> = res.chunks.iter()
+        .map(|(offset, chunk)| (*offset, chunk.chunk_type().to_string()))
+        .collect();
+    match res.chunks.first() {
+        Some((_, chunk)) if chunk.chunk_type().to_string() == "IHDR" => {
+            if chunk.data().len() != IHDR_LENGTH {
+                res.problem(Some(SIGNATURE.len()), format!("IHDR is {} bytes, expected {}", chunk.data().len(), IHDR_LENGTH));
+            }
+        }
+        _ => res.problem(Some(SIGNATURE.len()), "first chunk is not IHDR".to_string()),
+    }
+    for (offset, _) in types.iter().skip(1).filter(|(_, t)| t == "IHDR") {
+        res.problem(Some(*offset), "IHDR after the first chunk".to_string());
+    }
+    let idat: Vec<usize> = types.iter()
+        .enumerate()
+        .filter(|(_, (_, t))| t == "IDAT")
+        .map(|(i, _)| i)
+        .collect();
+    match (idat.first(), idat.last()) {
+        (Some(first), Some(last)) => {
+            if last - first + 1 != idat.len() {
+                res.problem(Some(types[*first].0), "IDAT chunks are not consecutive".to_string());
+            }
+            if let Some((offset, _)) = types.iter().skip(*first).find(|(_, t)| t == "PLTE") {
+                res.problem(Some(*offset), "PLTE after IDAT".to_string());
+            }
+        }
+        _ => res.problem(None, "no IDAT chunk".to_string()),
+    }
+    if !types.iter().any(|(_, t)| t == "IEND") {
+        res.problem(None, "missing IEND".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    fn testing_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        SIGNATURE.iter().copied().chain(chunks.concat()).collect()
+    }
+
+    fn messages<'a>(validation: &'a Validation) -> Vec<&'a str> {
+        validation.problems.iter().map(|problem| problem.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let bytes = testing_file(&[chunk("IHDR", &[0; 13]), chunk("IDAT", b"x"), chunk("IEND", b"")]);
+        let validation = validate(&bytes);
+        assert!(validation.is_valid());
+        assert_eq!(validation.chunks.len(), 3);
+        assert_eq!(validation.chunks[1].0, 33);
+    }
+
+    #[test]
+    fn test_bad_crc_and_trailing_data() {
+        let mut idat = chunk("IDAT", b"x");
+        idat[8] ^= 0xff;
+        let mut bytes = testing_file(&[chunk("IHDR", &[0; 13]), idat, chunk("IEND", b"")]);
+        bytes.extend_from_slice(&chunk("ruSt", b"x"));
+        bytes.extend_from_slice(b"hidden");
+        let validation = validate(&bytes);
+        assert_eq!(messages(&validation), vec!["CRC mismatch in IDAT", "ruSt after IEND", "6 bytes after IEND"]);
+        assert_eq!(validation.chunks.len(), 4);
+        assert_eq!(validation.problems[0].offset, Some(33));
+    }
+
+    #[test]
+    fn test_chunk_order() {
+        let bytes = testing_file(&[
+            chunk("IDAT", b"x"),
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"x"),
+            chunk("IDAT", b"x"),
+            chunk("PLTE", b"x"),
+        ]);
+        let validation = validate(&bytes);
+        assert_eq!(messages(&validation), vec![
+            "first chunk is not IHDR",
+            "IHDR after the first chunk",
+            "IDAT chunks are not consecutive",
+            "PLTE after IDAT",
+            "missing IEND",
+        ]);
+    }
+
+    #[test]
+    fn test_truncated_and_garbage() {
+        let mut bytes = testing_file(&[chunk("IHDR", &[0; 13]), chunk("IDAT", b"xyz")]);
+        bytes.truncate(bytes.len() - 2);
+        let validation = validate(&bytes);
+        assert!(messages(&validation)[0].starts_with("chunk length 3 runs past the end"));
+        assert!(!validate(b"not a png").is_valid());
+    }
+}